@@ -0,0 +1,199 @@
+//! things to do with the accepted entry
+
+use std::{
+    env,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use anyhow::Context;
+
+use crate::{source::QueryCommand, template::Template, EntryContext, SearchResultEntry};
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    /// replaces search-tui with a command, whose `executable` and `args` are
+    /// templates of the accepted entry
+    Exec(QueryCommand),
+    /// opens a templated url or path with the default application, e.g. a
+    /// browser
+    Open {
+        #[serde(default = "default_open_target")]
+        target: String,
+    },
+    /// sends a signal to the process whose pid is the entry identifier
+    Signal {
+        #[serde(default = "default_signal")]
+        signal: String,
+    },
+    /// copies a part of the entry to the clipboard
+    Copy {
+        #[serde(default)]
+        field: CopyField,
+    },
+}
+
+/// the part of an entry that is copied
+#[derive(Deserialize, JsonSchema, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum CopyField {
+    #[default]
+    Identifier,
+    Title,
+    /// the whole entry, serialized as json
+    Json,
+}
+
+impl CopyField {
+    pub fn of(self, entry: &SearchResultEntry) -> anyhow::Result<String> {
+        Ok(match self {
+            CopyField::Identifier => entry.identifier.clone(),
+            CopyField::Title => entry.title.clone(),
+            CopyField::Json => serde_json::to_string(entry)?,
+        })
+    }
+}
+
+fn default_open_target() -> String {
+    "{identifier}".into()
+}
+
+fn default_signal() -> String {
+    "TERM".into()
+}
+
+impl Action {
+    pub fn run(&self, entry: &SearchResultEntry) -> anyhow::Result<()> {
+        match self {
+            Action::Exec(command) => exec(command.build(&EntryContext::new(entry))?),
+            Action::Open { target } => {
+                let target = Template::new(target)?.render(&EntryContext::new(entry))?;
+                let status = opener().arg(&target).status()?;
+                status
+                    .exit_ok()
+                    .with_context(|| format!("unable to open {target}"))
+            }
+            Action::Signal { signal } => {
+                // never signal process groups (negative pids) or everything (-1)
+                let pid: u32 = entry
+                    .identifier
+                    .parse()
+                    .ok()
+                    .filter(|pid| *pid > 0)
+                    .ok_or_else(|| anyhow::anyhow!("{} is not a pid", entry.identifier))?;
+                let output = Command::new("kill")
+                    .args(["-s", signal, &pid.to_string()])
+                    .output()?;
+                output.status.exit_ok().map_err(|err| {
+                    anyhow::anyhow!(
+                        "unable to send SIG{signal} to {}: {}, {err}",
+                        entry.identifier,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    )
+                })
+            }
+            Action::Copy { field } => copy_to_clipboard(&field.of(entry)?),
+        }
+    }
+}
+
+/// a command bound to a key, which is run in the background for the selected
+/// entry without closing the picker
+#[derive(Deserialize, JsonSchema)]
+pub struct SilentAction {
+    pub key: String,
+    /// whose `executable` and `args` are templates of the selected entry
+    pub command: QueryCommand,
+}
+
+impl SilentAction {
+    /// runs the command, returning a message describing how it went
+    pub async fn run(&self, entry: &SearchResultEntry) -> String {
+        let mut command = match self.command.build(&EntryContext::new(entry)) {
+            Ok(command) => command,
+            Err(err) => return err.to_string(),
+        };
+        command.stdin(Stdio::null());
+        let output = tokio::task::spawn_blocking(move || command.output()).await;
+        let executable = &self.command.executable;
+        match output {
+            Ok(Ok(output)) if output.status.success() => format!("{executable} succeeded"),
+            Ok(Ok(output)) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                match stderr.lines().rfind(|line| !line.trim().is_empty()) {
+                    Some(line) => format!("{executable} failed ({}): {line}", output.status),
+                    None => format!("{executable} failed ({})", output.status),
+                }
+            }
+            Ok(Err(err)) => format!("unable to run {executable}: {err}"),
+            Err(err) => err.to_string(),
+        }
+    }
+}
+
+pub fn is_wayland() -> bool {
+    env::var_os("WAYLAND_DISPLAY").is_some()
+}
+
+/// copies `text` to the system clipboard, using `wl-copy` or `xclip`
+pub fn copy_to_clipboard(text: &str) -> anyhow::Result<()> {
+    let command = if is_wayland() {
+        Command::new("wl-copy")
+    } else {
+        let mut command = Command::new("xclip");
+        command.args(["-selection", "clipboard", "-i"]);
+        command
+    };
+    pipe_to(command, text)
+}
+
+/// runs `command` with `text` as its stdin
+pub fn pipe_to(mut command: Command, text: &str) -> anyhow::Result<()> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .with_context(|| format!("unable to run {program}"))?;
+    child
+        .stdin
+        .take()
+        .expect("child stdin is piped")
+        .write_all(text.as_bytes())?;
+    child
+        .wait()?
+        .exit_ok()
+        .with_context(|| format!("{program} failed"))
+}
+
+/// the platform's command for opening urls and files with their default
+/// application
+fn opener() -> Command {
+    if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        // the first quoted argument of start is the window title
+        command.args(["/c", "start", ""]);
+        command
+    } else {
+        Command::new("xdg-open")
+    }
+}
+
+#[cfg(unix)]
+fn exec(mut command: Command) -> anyhow::Result<()> {
+    use std::os::unix::process::CommandExt;
+
+    Err(command.exec().into())
+}
+
+#[cfg(not(unix))]
+fn exec(mut command: Command) -> anyhow::Result<()> {
+    let status = command.status()?;
+    std::process::exit(status.code().unwrap_or(1));
+}
@@ -0,0 +1,85 @@
+//! the results of recent queries, so that going back to one of them (e.g. by
+//! deleting the last character) doesn't run the backend again
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::SearchResult;
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// how long the results of a query are reused for
+    ttl_millis: u64,
+    /// how many queries are cached, the least recently searched ones being
+    /// evicted first
+    max_queries: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl_millis: 60_000,
+            max_queries: 100,
+        }
+    }
+}
+
+struct CachedResult {
+    query: String,
+    path: String,
+    searched_at: Instant,
+    result: SearchResult,
+}
+
+pub struct ResultCache {
+    ttl: Duration,
+    max_queries: usize,
+    /// the least recently searched query first
+    results: VecDeque<CachedResult>,
+}
+
+impl ResultCache {
+    pub fn new(config: &CacheConfig) -> Self {
+        Self {
+            ttl: Duration::from_millis(config.ttl_millis),
+            max_queries: config.max_queries,
+            results: VecDeque::new(),
+        }
+    }
+
+    /// the results of `query` in `path`, unless they have expired
+    pub fn get(&mut self, query: &str, path: &str) -> Option<SearchResult> {
+        self.results
+            .retain(|cached| cached.searched_at.elapsed() < self.ttl);
+        let cached = self
+            .results
+            .iter()
+            .find(|cached| cached.query == query && cached.path == path)?;
+        let mut result = cached.result.clone();
+        // the latency of the original search doesn't apply
+        result.duration = None;
+        Some(result)
+    }
+
+    pub fn insert(&mut self, query: String, path: String, result: SearchResult) {
+        self.results
+            .retain(|cached| cached.query != query || cached.path != path);
+        if self.results.len() >= self.max_queries {
+            self.results.pop_front();
+        }
+        if self.max_queries > 0 {
+            self.results.push_back(CachedResult {
+                query,
+                path,
+                searched_at: Instant::now(),
+                result,
+            });
+        }
+    }
+}
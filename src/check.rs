@@ -0,0 +1,166 @@
+//! `--check-config`, which reports the problems of a config that would
+//! otherwise only show up once the picker runs
+
+use std::{
+    env,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    action::Action,
+    frontend::Key,
+    source::{BuiltInSource, QueryCommand, Source},
+    template::Template,
+    Config,
+};
+
+/// a problem with the config, and where it is
+pub struct Problem {
+    pub location: String,
+    pub message: String,
+}
+
+#[derive(Default)]
+struct Checker {
+    problems: Vec<Problem>,
+}
+
+impl Checker {
+    fn report(&mut self, location: &str, message: impl Display) {
+        self.problems.push(Problem {
+            location: location.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    fn template(&mut self, location: &str, template: &str) {
+        if let Err(err) = Template::new(template) {
+            self.report(location, err);
+        }
+    }
+
+    /// the executable of templated commands can only be looked up if it
+    /// doesn't depend on the template variables
+    fn executable(&mut self, location: &str, executable: &str) {
+        if !executable.contains('{') && find_executable(executable).is_none() {
+            self.report(location, format_args!("{executable} not found"));
+        }
+    }
+
+    fn command(&mut self, location: &str, command: &QueryCommand) {
+        self.template(&format!("{location}.executable"), &command.executable);
+        self.executable(&format!("{location}.executable"), &command.executable);
+        for (index, arg) in command.args.iter().enumerate() {
+            self.template(&format!("{location}.args[{index}]"), arg);
+        }
+    }
+
+    fn source(&mut self, location: &str, source: &Source) {
+        match source {
+            Source::Command(command) => self.command(location, command),
+            Source::Persistent(persistent) => {
+                self.executable(&format!("{location}.executable"), &persistent.executable)
+            }
+            Source::Http(http) => {
+                self.template(&format!("{location}.url"), &http.url);
+                if let Some(body) = &http.body {
+                    self.template(&format!("{location}.body"), body);
+                }
+                for (name, value) in &http.headers {
+                    self.template(&format!("{location}.headers.{name}"), value);
+                }
+            }
+            #[cfg(unix)]
+            Source::Socket(_) => {}
+            Source::BuiltIn(BuiltInSource::Stdin(stdin)) => {
+                if let Err(err) = stdin.check() {
+                    self.report(location, err);
+                }
+            }
+            Source::BuiltIn(BuiltInSource::WebSuggestions(web)) => {
+                self.template(&format!("{location}.url"), &web.url)
+            }
+            Source::BuiltIn(_) => {}
+            Source::Merged(merged) => {
+                for (index, source) in merged.sources.iter().enumerate() {
+                    self.source(&format!("{location}[{index}]"), &source.source);
+                }
+            }
+        }
+    }
+
+    fn action(&mut self, location: &str, action: &Action) {
+        match action {
+            Action::Exec(command) => self.command(location, command),
+            Action::Open { target } => self.template(&format!("{location}.target"), target),
+            Action::Signal { .. } | Action::Copy { .. } => {}
+        }
+    }
+}
+
+/// the location of an `exec` accept action in a config with `multi_select`,
+/// which could only run for one of the marked entries since it replaces the
+/// process
+pub fn exec_with_multi_select(config: &Config) -> Option<&'static str> {
+    match config.accept_action {
+        Some(Action::Exec(_)) if config.multi_select => Some("accept_action"),
+        _ => None,
+    }
+}
+
+/// checks the templates, commands and keys of `config`
+pub fn check(config: &Config) -> Vec<Problem> {
+    let mut checker = Checker::default();
+    checker.template("display_template", &config.display_template);
+    checker.template("output_template", &config.output_template);
+    checker.template("prompt", &config.prompt);
+    if let Some(header) = &config.header {
+        checker.template("header", header);
+    }
+
+    checker.source("source", &config.source);
+
+    if let Some(preview) = &config.preview {
+        checker.command("preview.command", &preview.command);
+    }
+    if let Some(action) = &config.accept_action {
+        checker.action("accept_action", action);
+    }
+    if let Some(create) = &config.create_entry {
+        checker.template("create_entry.title", &create.title);
+        if let Some(template) = &create.output_template {
+            checker.template("create_entry.output_template", template);
+        }
+        if let Some(action) = &create.action {
+            checker.action("create_entry.action", action);
+        }
+    }
+    if let Some(location) = exec_with_multi_select(config) {
+        checker.report(location, "exec can't be used with multi_select");
+    }
+    for (index, action) in config.execute_silent.iter().enumerate() {
+        if let Err(err) = Key::parse(&action.key) {
+            checker.report(&format!("execute_silent[{index}].key"), err);
+        }
+        checker.command(&format!("execute_silent[{index}].command"), &action.command);
+    }
+    for key in config.keybindings.keys() {
+        if let Err(err) = Key::parse(key) {
+            checker.report(&format!("keybindings.{key}"), err);
+        }
+    }
+
+    checker.problems
+}
+
+/// the path of `executable`, looked up in `PATH` unless it is a path
+fn find_executable(executable: &str) -> Option<PathBuf> {
+    let path = Path::new(executable);
+    if path.components().count() > 1 {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(executable))
+        .find(|path| path.is_file())
+}
@@ -0,0 +1,94 @@
+//! the command line options, most of which override a config field
+
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::{Config, ConfigFormat, Height};
+
+#[derive(Parser)]
+#[command(version, about = "flexible tui application to search for stuff")]
+pub struct Cli {
+    /// reads the config from this file instead of stdin
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+    /// selects a profile of a config with several of them
+    #[arg(long)]
+    pub profile: Option<String>,
+    /// reports the problems of the config (like invalid templates or missing
+    /// executables) instead of searching
+    #[arg(long)]
+    pub check_config: bool,
+    /// writes an annotated starter config to this path, `-` for stdout, or
+    /// `$XDG_CONFIG_HOME/search-tui/config.toml` if no path is given
+    #[arg(long, value_name = "PATH")]
+    pub init_config: Option<Option<PathBuf>>,
+    /// prints the json schema of the config and exits
+    #[arg(long)]
+    pub schema: bool,
+    /// the format of the config, guessed if unset
+    #[arg(long, value_enum)]
+    pub config_format: Option<ConfigFormat>,
+    /// overrides `name`
+    #[arg(long)]
+    name: Option<String>,
+    /// overrides `prompt`
+    #[arg(long)]
+    prompt: Option<String>,
+    /// overrides `timeout_millis`
+    #[arg(long)]
+    timeout_millis: Option<u64>,
+    /// overrides `display_template`
+    #[arg(long)]
+    display_template: Option<String>,
+    /// overrides `output_template`
+    #[arg(long)]
+    output_template: Option<String>,
+    /// enables `alternate_screen`
+    #[arg(long)]
+    alternate_screen: bool,
+    /// overrides `height`, e.g. 10 or 40%
+    #[arg(long)]
+    height: Option<Height>,
+    /// overrides the `delimiter` of a stdin source
+    #[arg(long)]
+    pub delimiter: Option<String>,
+    /// overrides the `nth` of a stdin source
+    #[arg(long)]
+    pub nth: Option<String>,
+    /// overrides the `with_nth` of a stdin source
+    #[arg(long)]
+    pub with_nth: Option<String>,
+    /// reads NUL-terminated items with a stdin source
+    #[arg(long)]
+    pub read0: bool,
+    /// terminates the printed entries with NUL instead of newlines
+    #[arg(long)]
+    pub print0: bool,
+    /// records the clipboard for a clipboard source instead of searching it
+    #[cfg(feature = "clipboard")]
+    #[arg(long)]
+    pub clipboard_daemon: bool,
+}
+
+impl Cli {
+    /// overrides the fields of `config` given on the command line
+    pub fn apply(&self, config: &mut Config) {
+        let override_with = |field: &mut String, value: &Option<String>| {
+            if let Some(value) = value {
+                field.clone_from(value);
+            }
+        };
+        override_with(&mut config.name, &self.name);
+        override_with(&mut config.prompt, &self.prompt);
+        override_with(&mut config.display_template, &self.display_template);
+        override_with(&mut config.output_template, &self.output_template);
+        if let Some(timeout_millis) = self.timeout_millis {
+            config.timeout_millis = timeout_millis;
+        }
+        config.alternate_screen |= self.alternate_screen;
+        if let Some(height) = self.height {
+            config.height = Some(height);
+        }
+    }
+}
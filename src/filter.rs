@@ -0,0 +1,97 @@
+//! in-process filtering for sources that can't search by themselves
+
+use crate::{SearchResult, SearchResultEntry};
+
+/// keeps the entries whose title matches `query`, with their confidence set to
+/// the match score and sorted by it
+pub fn filter(entries: impl IntoIterator<Item = SearchResultEntry>, query: &str) -> SearchResult {
+    filter_by(
+        entries.into_iter().map(|entry| {
+            let title = entry.title.clone();
+            (entry, title)
+        }),
+        query,
+    )
+}
+
+/// like [`filter`], but matches `query` against the text paired with each
+/// entry instead of its title
+pub fn filter_by(
+    entries: impl IntoIterator<Item = (SearchResultEntry, String)>,
+    query: &str,
+) -> SearchResult {
+    let query = query.to_lowercase();
+    let mut results: Vec<_> = entries
+        .into_iter()
+        .filter_map(|(mut entry, text)| {
+            entry.confidence = score(&text, &query)?;
+            Some(entry)
+        })
+        .collect();
+    // stable sort, so equally good matches keep the source order
+    results.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    SearchResult::new(results)
+}
+
+/// the match score of `title` against `query` (between 0 and 1), 0 if it
+/// doesn't match
+pub fn match_score(title: &str, query: &str) -> f64 {
+    score(title, &query.to_lowercase()).unwrap_or(0.0)
+}
+
+/// whether `title` matches `query`, like in [`filter`]
+pub fn matches(title: &str, query: &str) -> bool {
+    score(title, &query.to_lowercase()).is_some()
+}
+
+/// the indices of the chars of `title` that match `query`, like in [`filter`].
+/// the first substring match is preferred over the earliest subsequence match
+pub fn match_indices(title: &[char], query: &str) -> Vec<usize> {
+    let query: Vec<char> = query.chars().flat_map(char::to_lowercase).collect();
+    if query.is_empty() {
+        return Vec::new();
+    }
+
+    let eq = |c: char, q: char| c.to_lowercase().eq([q]);
+    let substring = (0..title.len().saturating_sub(query.len() - 1))
+        .find(|&start| query.iter().zip(&title[start..]).all(|(&q, &c)| eq(c, q)));
+    if let Some(start) = substring {
+        return (start..start + query.len()).collect();
+    }
+
+    let mut indices = Vec::with_capacity(query.len());
+    let mut query = query.iter().peekable();
+    for (index, &c) in title.iter().enumerate() {
+        if query.next_if(|&&q| eq(c, q)).is_some() {
+            indices.push(index);
+        }
+    }
+    if query.peek().is_some() {
+        indices.clear();
+    }
+    indices
+}
+
+/// scores a lowercase `query` against `title`, case-insensitively. substring
+/// matches score above subsequence matches, and matches covering more of the
+/// title score higher. returns `None` if the title doesn't match.
+fn score(title: &str, query: &str) -> Option<f64> {
+    if query.is_empty() {
+        return Some(1.0);
+    }
+
+    let title = title.to_lowercase();
+    let coverage = (query.chars().count() as f64 / title.chars().count() as f64).min(1.0);
+    if title.contains(query) {
+        Some(0.5 + 0.5 * coverage)
+    } else if is_subsequence(query, &title) {
+        Some(0.5 * coverage)
+    } else {
+        None
+    }
+}
+
+fn is_subsequence(needle: &str, haystack: &str) -> bool {
+    let mut haystack = haystack.chars();
+    needle.chars().all(|c| haystack.any(|h| h == c))
+}
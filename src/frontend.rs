@@ -0,0 +1,38 @@
+//! the user interfaces of the picker, which display a [`State`] and turn user
+//! events into [`Input`]s for it
+
+mod terminal;
+
+pub use terminal::{Key, KeyAction, TerminalFrontend};
+
+use crate::state::{Input, State};
+
+pub trait Frontend {
+    /// waits for the next event
+    async fn poll(&mut self) -> anyhow::Result<FrontendEvent>;
+
+    /// redraws the parts of the interface affected by `change`
+    fn render(&mut self, state: &State, change: Change) -> anyhow::Result<()>;
+
+    /// shows an error, e.g. from copying an entry, until the query line is
+    /// redrawn (when the query is edited or the cursor moves). search errors
+    /// are kept by the state instead, see [`State::search_error`]
+    fn show_error(&mut self, state: &State, message: &str) -> anyhow::Result<()>;
+}
+
+pub enum FrontendEvent {
+    Input(Input),
+    /// the whole interface needs to be redrawn, e.g. after a resize
+    Redraw,
+}
+
+pub enum Change {
+    Query,
+    /// the result list has been replaced
+    Results,
+    Selection,
+    Preview,
+    /// the status line above the results
+    Status,
+    All,
+}
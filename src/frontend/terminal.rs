@@ -0,0 +1,1142 @@
+//! the terminal user interface, drawn below the cursor position at startup
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io::{stdout, Stdout},
+    time::{Duration, Instant},
+};
+
+use crossterm::{
+    cursor::{Hide, MoveTo, MoveToColumn, MoveUp, Show},
+    event::{
+        DisableMouseCapture, EnableMouseCapture, Event, EventStream, KeyCode, KeyEvent,
+        KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+    },
+    execute, queue,
+    style::{Attribute, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor},
+    terminal::{
+        disable_raw_mode, enable_raw_mode, size, Clear, ClearType, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
+    Command,
+};
+use futures::{pin_mut, FutureExt, StreamExt};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use unicode_width::UnicodeWidthStr;
+
+use super::{Change, Frontend, FrontendEvent};
+use crate::{
+    action::CopyField,
+    filter,
+    preview::PreviewPosition,
+    state::{Input, Row, State},
+    template::Template,
+    text::{
+        reapply_after_resets, sanitize, truncate_to_width, truncate_with_ellipsis,
+        visible_char_indices, visible_tail,
+    },
+    AnsiMode, Config, CursorPositioning, NavigationMode, SearchResultEntry, SortOrder,
+};
+use color::Colors;
+use cursor::Cursor;
+pub use keys::{Key, KeyAction};
+
+mod color;
+mod cursor;
+mod keys;
+
+pub struct TerminalFrontend<'a> {
+    config: &'a Config,
+    out: Stdout,
+    cursor: Cursor,
+    colors: Colors,
+    event_stream: EventStream,
+    display_template: Template<'a>,
+    prompt_template: Template<'a>,
+    header_template: Option<Template<'a>>,
+    create_template: Option<Template<'a>>,
+    scroll_offset: usize,
+    row_cache: RowCache,
+    /// the width the cached rows were truncated to
+    list_width: usize,
+    /// the number of results shown at once, which page up and down move by
+    page_size: usize,
+    /// the index of the current preview layout
+    preview_layout: usize,
+    /// the keys of the `execute_silent` actions
+    silent_keys: Vec<Key>,
+    /// the keys bound with `keybindings`, which take precedence over the
+    /// default ones
+    key_bindings: Vec<(Key, KeyAction)>,
+    /// the index of the row drawn on each line below the query line, for
+    /// finding the row that was clicked
+    clicked_rows: Vec<Option<usize>>,
+    /// the row and time of the last click, for detecting double clicks
+    last_click: Option<(usize, Instant)>,
+    /// the last error other than search errors, which are kept by the state,
+    /// shown above the results until the query changes
+    error: Option<String>,
+    /// whether the last rendered state was searching, which animates the
+    /// spinner
+    searching: bool,
+    spinner_frame: usize,
+}
+
+/// two clicks on the same row within this time accept it
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(400);
+
+const PINNED_MARKER: &str = "★ ";
+
+const SPINNER_FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+const SPINNER_INTERVAL: Duration = Duration::from_millis(80);
+
+impl<'a> TerminalFrontend<'a> {
+    pub fn new(config: &'a Config) -> anyhow::Result<Self> {
+        let mut out = stdout();
+        enable_raw_mode()?;
+        if config.alternate_screen {
+            execute!(out, EnterAlternateScreen, MoveTo(0, 0))?;
+        }
+        let silent_keys = config
+            .execute_silent
+            .iter()
+            .map(|action| Key::parse(&action.key))
+            .collect::<anyhow::Result<_>>()?;
+        let key_bindings = config
+            .keybindings
+            .iter()
+            .map(|(key, action)| Ok((Key::parse(key)?, *action)))
+            .collect::<anyhow::Result<_>>()?;
+        reserve_rows(&mut out, config)?;
+        let mut cursor = Cursor::new(cursor_positioning(config))?;
+        cursor.save(&mut out, 0)?;
+        if config.mouse {
+            execute!(out, EnableMouseCapture)?;
+        }
+        let mut frontend = Self {
+            config,
+            out,
+            cursor,
+            colors: Colors::new(config.color_depth),
+            event_stream: EventStream::new(),
+            display_template: Template::new(&config.display_template)?,
+            prompt_template: Template::new(&config.prompt)?,
+            header_template: config.header.as_deref().map(Template::new).transpose()?,
+            create_template: config
+                .create_entry
+                .as_ref()
+                .map(|create| Template::new(&create.title))
+                .transpose()?,
+            scroll_offset: 0,
+            row_cache: RowCache::new(&config.display_template),
+            list_width: 0,
+            page_size: 1,
+            preview_layout: 0,
+            silent_keys,
+            key_bindings,
+            clicked_rows: Vec::new(),
+            last_click: None,
+            error: None,
+            searching: false,
+            spinner_frame: 0,
+        };
+        let state = State::new(config);
+        frontend.update_query(&state)?;
+        // the header is shown before the first search finishes
+        if config.header.is_some() {
+            frontend.update_results(&state)?;
+        }
+        Ok(frontend)
+    }
+}
+
+impl TerminalFrontend<'_> {
+    /// stops the process group like ctrl+z does outside of raw mode, which
+    /// is disabled while the picker is stopped. the picker is drawn again on
+    /// a new line once it is continued (e.g. with `fg`)
+    #[cfg(unix)]
+    fn suspend(&mut self) -> anyhow::Result<()> {
+        execute!(self.out, Print("\r"), Clear(ClearType::FromCursorDown))?;
+        if self.config.mouse {
+            execute!(self.out, DisableMouseCapture)?;
+        }
+        if self.config.alternate_screen {
+            execute!(self.out, LeaveAlternateScreen)?;
+        }
+        disable_raw_mode()?;
+        // returns once the process is continued
+        let status = std::process::Command::new("kill")
+            .args(["-s", "TSTP", "0"])
+            .status();
+        enable_raw_mode()?;
+        status?.exit_ok()?;
+        if self.config.alternate_screen {
+            execute!(self.out, EnterAlternateScreen, MoveTo(0, 0))?;
+        }
+        if self.config.mouse {
+            execute!(self.out, EnableMouseCapture)?;
+        }
+        reserve_rows(&mut self.out, self.config)?;
+        self.cursor = Cursor::new(cursor_positioning(self.config))?;
+        self.cursor.save(&mut self.out, 0)?;
+        self.row_cache.invalidate();
+        Ok(())
+    }
+}
+
+impl TerminalFrontend<'_> {
+    fn mouse_input(&mut self, mouse: MouseEvent) -> Option<Input> {
+        match mouse.kind {
+            MouseEventKind::ScrollUp => Some(Input::SelectPrevious),
+            MouseEventKind::ScrollDown => Some(Input::SelectNext),
+            MouseEventKind::Down(MouseButton::Left) => {
+                let line = mouse.row.checked_sub(self.cursor.row()?)?;
+                let row = (*self.clicked_rows.get(usize::from(line))?)?;
+                let now = Instant::now();
+                let double_click = self.last_click.is_some_and(|(last_row, time)| {
+                    last_row == row && now.duration_since(time) < DOUBLE_CLICK_TIME
+                });
+                if double_click {
+                    self.last_click = None;
+                    Some(Input::Accept)
+                } else {
+                    self.last_click = Some((row, now));
+                    Some(Input::Select(row))
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// makes room for the `height` of the picker below the cursor, scrolling the
+/// terminal if needed, so that the picker never scrolls it itself
+fn reserve_rows(out: &mut Stdout, config: &Config) -> anyhow::Result<()> {
+    let Some(height) = config.height else {
+        return Ok(());
+    };
+    let rows_below = height.rows(size()?.1) - 1;
+    execute!(
+        out,
+        Print("\r\n".repeat(rows_below.into())),
+        MoveUp(rows_below)
+    )?;
+    Ok(())
+}
+
+/// clicks are mapped to rows using the row of the query line, which is only
+/// known with absolute positioning
+fn cursor_positioning(config: &Config) -> CursorPositioning {
+    if config.mouse {
+        CursorPositioning::Absolute
+    } else {
+        config.cursor_positioning
+    }
+}
+
+impl Drop for TerminalFrontend<'_> {
+    fn drop(&mut self) {
+        let _ = execute!(self.out, Print("\r"), Clear(ClearType::FromCursorDown));
+        if self.config.mouse {
+            let _ = execute!(self.out, DisableMouseCapture);
+        }
+        if self.config.alternate_screen {
+            let _ = execute!(self.out, LeaveAlternateScreen);
+        }
+        let _ = disable_raw_mode();
+    }
+}
+
+impl Frontend for TerminalFrontend<'_> {
+    async fn poll(&mut self) -> anyhow::Result<FrontendEvent> {
+        loop {
+            let event = if self.config.spinner && self.searching {
+                let next_event = self.event_stream.next().fuse();
+                let next_frame = tokio::time::sleep(SPINNER_INTERVAL).fuse();
+                pin_mut!(next_event, next_frame);
+                futures::select! {
+                    event = next_event => event,
+                    () = next_frame => {
+                        self.spinner_frame += 1;
+                        self.draw_spinner()?;
+                        continue;
+                    }
+                }
+            } else {
+                self.event_stream.next().await
+            };
+            if let Some(Ok(Event::Key(key))) = &event {
+                let bound = self.key_bindings.iter().find(|(k, _)| k.matches(key));
+                if let Some((_, action)) = bound {
+                    return Ok(FrontendEvent::Input(action.input(self.page_size)));
+                }
+            }
+            match event {
+                Some(Ok(Event::Key(key)))
+                    if key.code == KeyCode::Char('l') && key.modifiers == KeyModifiers::ALT =>
+                {
+                    // the preview layout is purely presentational, so it is
+                    // handled here instead of by the picker state
+                    if self.config.preview.is_some() {
+                        self.preview_layout += 1;
+                        return Ok(FrontendEvent::Redraw);
+                    }
+                }
+                #[cfg(unix)]
+                Some(Ok(Event::Key(key)))
+                    if key.code == KeyCode::Char('z') && key.modifiers == KeyModifiers::CONTROL =>
+                {
+                    self.suspend()?;
+                    return Ok(FrontendEvent::Redraw);
+                }
+                Some(Ok(Event::Key(key))) => {
+                    let silent_action = self.silent_keys.iter().position(|k| k.matches(&key));
+                    if let Some(index) = silent_action {
+                        return Ok(FrontendEvent::Input(Input::ExecuteSilent(index)));
+                    }
+                    if let Some(input) = key_input(key, self.page_size) {
+                        return Ok(FrontendEvent::Input(input));
+                    }
+                }
+                Some(Ok(Event::Mouse(mouse))) => {
+                    if let Some(input) = self.mouse_input(mouse) {
+                        return Ok(FrontendEvent::Input(input));
+                    }
+                }
+                Some(Ok(Event::Resize(_, _))) => {
+                    self.row_cache.invalidate();
+                    return Ok(FrontendEvent::Redraw);
+                }
+                Some(Ok(_)) => {}
+                Some(Err(error)) => return Err(error.into()),
+                None => return Ok(FrontendEvent::Input(Input::Cancel)),
+            }
+        }
+    }
+
+    fn render(&mut self, state: &State, change: Change) -> anyhow::Result<()> {
+        if self.searching != state.searching() {
+            self.searching = state.searching();
+            self.draw_spinner()?;
+        }
+        match change {
+            Change::Query => {
+                // the error is drawn with the results, which are redrawn once the
+                // search for the new query is done
+                self.error = None;
+                self.update_query(state)
+            }
+            Change::Results => {
+                // the prompt may show the number of results
+                self.update_query(state)?;
+                self.row_cache.results_changed();
+                self.update_results(state)
+            }
+            Change::Selection | Change::Preview | Change::Status => self.update_results(state),
+            Change::All => {
+                self.update_query(state)?;
+                self.update_results(state)
+            }
+        }
+    }
+
+    fn show_error(&mut self, state: &State, message: &str) -> anyhow::Result<()> {
+        self.error = Some(message.to_string());
+        self.update_results(state)
+    }
+}
+
+/// `text` with the parts that match `query` wrapped in `start` and `end`
+fn highlight_matches<'a>(text: &'a str, query: &str, start: &str, end: &str) -> Cow<'a, str> {
+    let chars = visible_char_indices(text);
+    let visible: Vec<char> = chars.iter().map(|(_, c)| *c).collect();
+    let indices = filter::match_indices(&visible, query);
+    if indices.is_empty() {
+        return Cow::Borrowed(text);
+    }
+
+    let mut highlighted = String::with_capacity(text.len());
+    let mut copied = 0;
+    for (position, &index) in indices.iter().enumerate() {
+        let (byte_index, c) = chars[index];
+        if position == 0 || indices[position - 1] + 1 != index {
+            highlighted.push_str(&text[copied..byte_index]);
+            highlighted.push_str(start);
+            copied = byte_index;
+        }
+        if indices.get(position + 1) != Some(&(index + 1)) {
+            let end_index = byte_index + c.len_utf8();
+            highlighted.push_str(&text[copied..end_index]);
+            highlighted.push_str(end);
+            copied = end_index;
+        }
+    }
+    highlighted.push_str(&text[copied..]);
+    Cow::Owned(highlighted)
+}
+
+/// maps a key press to its picker input
+fn key_input(key: KeyEvent, page_size: usize) -> Option<Input> {
+    match key.code {
+        KeyCode::Char('s') if key.modifiers == KeyModifiers::CONTROL => Some(Input::ToggleSort),
+        KeyCode::Char('r') if key.modifiers == KeyModifiers::CONTROL => Some(Input::ToggleHistory),
+        KeyCode::Char('p') if key.modifiers == KeyModifiers::CONTROL => Some(Input::RecallPrevious),
+        KeyCode::Char('n') if key.modifiers == KeyModifiers::CONTROL => Some(Input::RecallNext),
+        KeyCode::Char('p') if key.modifiers == KeyModifiers::ALT => Some(Input::TogglePreview),
+        KeyCode::Char('s') if key.modifiers == KeyModifiers::ALT => Some(Input::ToggleCollapse),
+        KeyCode::Char('i') if key.modifiers == KeyModifiers::ALT => Some(Input::TogglePin),
+        KeyCode::Char('r') if key.modifiers == KeyModifiers::ALT => Some(Input::RefreshPreview),
+        KeyCode::Char('c') if key.modifiers == KeyModifiers::ALT => {
+            Some(Input::Copy(CopyField::Identifier))
+        }
+        KeyCode::Char('t') if key.modifiers == KeyModifiers::ALT => {
+            Some(Input::Copy(CopyField::Title))
+        }
+        KeyCode::Char('j') if key.modifiers == KeyModifiers::ALT => {
+            Some(Input::Copy(CopyField::Json))
+        }
+        KeyCode::Char('w') if key.modifiers == KeyModifiers::CONTROL => {
+            Some(Input::DeleteWordBackward)
+        }
+        KeyCode::Char('b') if key.modifiers == KeyModifiers::ALT => Some(Input::MoveWordLeft),
+        KeyCode::Char('f') if key.modifiers == KeyModifiers::ALT => Some(Input::MoveWordRight),
+        KeyCode::Char('u') if key.modifiers == KeyModifiers::CONTROL => Some(Input::KillToStart),
+        KeyCode::Char('k') if key.modifiers == KeyModifiers::CONTROL => Some(Input::KillToEnd),
+        KeyCode::Char('y') if key.modifiers == KeyModifiers::CONTROL => Some(Input::Yank),
+        KeyCode::Char('y') if key.modifiers == KeyModifiers::ALT => Some(Input::YankPop),
+        // terminals send ctrl+_ and ctrl+/ as ctrl+7
+        KeyCode::Char('_' | '/' | '7') if key.modifiers == KeyModifiers::CONTROL => {
+            Some(Input::Undo)
+        }
+        KeyCode::Char('/') if key.modifiers == KeyModifiers::ALT => Some(Input::Redo),
+        KeyCode::Backspace if key.modifiers == KeyModifiers::ALT => Some(Input::DeleteWordBackward),
+        KeyCode::Left if key.modifiers == KeyModifiers::CONTROL => Some(Input::MoveWordLeft),
+        KeyCode::Right if key.modifiers == KeyModifiers::CONTROL => Some(Input::MoveWordRight),
+        KeyCode::Char(c) => Some(Input::Insert(c)),
+        KeyCode::Tab => Some(Input::ToggleMark),
+        KeyCode::Backspace => Some(Input::DeleteBackward),
+        KeyCode::Delete => Some(Input::DeleteForward),
+        KeyCode::Left => Some(Input::MoveCursorLeft),
+        KeyCode::Right => Some(Input::MoveCursorRight),
+        KeyCode::Home => Some(Input::MoveCursorStart),
+        KeyCode::End => Some(Input::MoveCursorEnd),
+        KeyCode::Up => Some(Input::SelectPrevious),
+        KeyCode::Down => Some(Input::SelectNext),
+        KeyCode::PageUp => Some(Input::SelectPreviousPage(page_size)),
+        KeyCode::PageDown => Some(Input::SelectNextPage(page_size)),
+        KeyCode::Esc => Some(Input::Cancel),
+        KeyCode::Enter => Some(Input::Accept),
+        _ => None,
+    }
+}
+
+impl TerminalFrontend<'_> {
+    fn update_query(&mut self, state: &State) -> anyhow::Result<()> {
+        let prompt = self
+            .prompt_template
+            .render(&PromptContext::new(self.config, state))?;
+        // the query is scrolled horizontally so that it never wraps (which would
+        // break the saved cursor position), one cell is left free for the cursor
+        let term_width: usize = size()?.0.into();
+        let (prompt, prompt_width) =
+            truncate_to_width(&sanitize(&prompt), term_width.saturating_sub(1));
+        // the spinner takes the last cell and one is left free before it
+        let spinner_width = if self.config.spinner { 2 } else { 0 };
+        let max_width = term_width.saturating_sub(prompt_width + 1 + spinner_width);
+        self.cursor.restore(&mut self.out)?;
+        queue!(
+            self.out,
+            Print("\r"),
+            Clear(ClearType::UntilNewLine),
+            SetForegroundColor(self.colors.map(self.config.theme.prompt.0)),
+            Print(prompt),
+            ResetColor
+        )?;
+        // the part before the edit cursor is scrolled to keep the cursor in
+        // view, the part after it is cut off at the end of the line
+        let (before, after) = state.query().split_at(state.query_cursor());
+        let before = visible_tail(before, max_width);
+        let cursor_column = prompt_width + before.width();
+        let (after, _) = truncate_to_width(after, max_width - before.width());
+        let query = format!("{before}{after}");
+        if state.query_selected() {
+            // a selected query is replaced by typing, like a selected text field
+            queue!(
+                self.out,
+                SetAttribute(Attribute::Reverse),
+                Print(query),
+                SetAttribute(Attribute::NoReverse)
+            )?;
+        } else if let Some(placeholder) = self
+            .config
+            .placeholder
+            .as_ref()
+            .filter(|_| query.is_empty())
+        {
+            // the cursor stays at the start of the placeholder
+            let (placeholder, _) = truncate_to_width(&sanitize(placeholder), max_width);
+            queue!(
+                self.out,
+                SetAttribute(Attribute::Dim),
+                Print(placeholder),
+                SetAttribute(Attribute::NormalIntensity)
+            )?;
+        } else {
+            queue!(self.out, Print(query))?;
+        }
+        queue!(
+            self.out,
+            MoveToColumn(cursor_column.try_into().unwrap_or(u16::MAX))
+        )?;
+        self.cursor.save(&mut self.out, cursor_column)?;
+        // clearing the line has removed the spinner
+        self.draw_spinner()
+    }
+}
+
+impl TerminalFrontend<'_> {
+    /// draws the spinner at the end of the query line if a search is running,
+    /// or clears it otherwise
+    fn draw_spinner(&mut self) -> anyhow::Result<()> {
+        if self.config.spinner {
+            let frame = if self.searching {
+                SPINNER_FRAMES[self.spinner_frame % SPINNER_FRAMES.len()]
+            } else {
+                ' '
+            };
+            let term_width = size()?.0;
+            self.cursor.restore(&mut self.out)?;
+            queue!(
+                self.out,
+                MoveToColumn(term_width.saturating_sub(1)),
+                SetForegroundColor(self.colors.map(self.config.theme.status.0)),
+                Print(frame),
+                ResetColor
+            )?;
+            self.cursor.restore(&mut self.out)?;
+        }
+        execute!(self.out)?;
+        Ok(())
+    }
+
+    fn update_results(&mut self, state: &State) -> anyhow::Result<()> {
+        // the cursor is hidden while the list is redrawn, and shown again once it
+        // is back at the query position
+        execute!(self.out, Hide, Clear(ClearType::FromCursorDown))?;
+        let rows = self.draw_results(state);
+        self.cursor.printed_rows(*rows.as_ref().unwrap_or(&0))?;
+        self.cursor.restore(&mut self.out)?;
+        execute!(self.out, Show)?;
+        rows.map(|_| ())
+    }
+
+    /// draws the results below the query line, returning the number of rows
+    /// printed
+    fn draw_results(&mut self, state: &State) -> anyhow::Result<usize> {
+        #[derive(Serialize)]
+        struct Context<'a> {
+            identifier: &'a str,
+            title: &'a str,
+            confidence: f64,
+            index: usize,
+            one_based_index: usize,
+            display_index: usize,
+            one_based_display_index: usize,
+            extra: Map<String, Value>,
+        }
+
+        let Self {
+            config,
+            out,
+            scroll_offset,
+            display_template,
+            row_cache,
+            list_width,
+            page_size,
+            preview_layout,
+            colors,
+            create_template,
+            clicked_rows,
+            error,
+            header_template,
+            ..
+        } = self;
+        let theme = &config.theme;
+
+        let mut rows = 0;
+        // the query line
+        clicked_rows.clear();
+        clicked_rows.push(None);
+        let (term_width, term_height) = size()?;
+        let term_width: usize = term_width.into();
+        // the rows available to the picker, including the query line
+        let height = config
+            .height
+            .map_or(term_height, |height| height.rows(term_height));
+        if let Some(template) = header_template {
+            let header = template.render(&PromptContext::new(config, state))?;
+            for line in header.lines() {
+                let (line, _) = truncate_to_width(&sanitize(line), term_width);
+                rows += 1;
+                clicked_rows.push(None);
+                queue!(
+                    out,
+                    Print("\r\n"),
+                    SetForegroundColor(colors.map(theme.header.0)),
+                    Print(line),
+                    ResetColor
+                )?;
+            }
+        }
+        // errors are truncated like the status line, so that they never wrap
+        for error in state.search_error().into_iter().chain(error.as_deref()) {
+            let (error, _) = truncate_to_width(&sanitize(error), term_width);
+            rows += 1;
+            clicked_rows.push(None);
+            queue!(
+                out,
+                Print("\r\n"),
+                SetForegroundColor(colors.map(theme.error.0)),
+                Print(error),
+                ResetColor
+            )?;
+        }
+        let selected_index = state.selected_index();
+        if let Some(result) = state.result() {
+            // the status (warnings, the search latency, the number of results
+            // from each source, whether the query history is shown, the sort
+            // order unless it is the default and the outcome of the last
+            // background command) takes up the first row of the list
+            let mut status = result.warnings.clone();
+            let slow = result.duration.is_some_and(|duration| {
+                duration > Duration::from_millis(config.slow_search_millis)
+            });
+            if config.status_bar {
+                // the status bar starts with the number of results, the search
+                // latency, the source of the selected entry and its position
+                let count = result.results.len();
+                let plural = if count == 1 { "" } else { "s" };
+                let mut summary = vec![match result.duration {
+                    Some(duration) => {
+                        format!("{count} result{plural} in {} ms", duration.as_millis())
+                    }
+                    None => format!("{count} result{plural}"),
+                }];
+                if let Some(Row::Entry(_, entry)) = state.row(selected_index) {
+                    summary.extend(
+                        entry
+                            .source()
+                            .map(|source| config.ansi.clean(source).into()),
+                    );
+                }
+                if state.num_rows() > 0 {
+                    summary.push(format!("{}/{}", selected_index + 1, state.num_rows()));
+                }
+                status.splice(0..0, summary);
+            } else if let Some(duration) = result.duration.filter(|_| config.show_latency || slow) {
+                let millis = duration.as_millis();
+                status.push(if slow {
+                    format!("slow search: {millis} ms")
+                } else {
+                    format!("{millis} ms")
+                });
+            }
+            if !state.sources().is_empty() {
+                let sources: Vec<_> = state
+                    .sources()
+                    .iter()
+                    .map(|source| {
+                        let collapsed = if source.collapsed { " (collapsed)" } else { "" };
+                        format!("{} {}{collapsed}", source.name, source.count)
+                    })
+                    .collect();
+                status.push(sources.join(" · "));
+            }
+            if state.in_history() {
+                status.push("query history (enter to search, esc to go back)".into());
+            }
+            if state.sort_order() != SortOrder::Backend {
+                status.push(format!("sorted by {}", state.sort_order().name()));
+            }
+            if state.num_marked() > 0 {
+                status.push(format!("{} marked", state.num_marked()));
+            }
+            status.extend(state.message().map(str::to_string));
+            let mut max_results_shown = usize::from(height.max(2) - 2).saturating_sub(rows);
+            let status = (!status.is_empty()).then(|| {
+                let (status, _) = truncate_to_width(&sanitize(&status.join(", ")), term_width);
+                let color = if slow { theme.error.0 } else { theme.status.0 };
+                (status, SetForegroundColor(colors.map(color)))
+            });
+            if let Some((status, color)) = status.as_ref().filter(|_| !config.status_bar) {
+                rows += 1;
+                clicked_rows.push(None);
+                queue!(out, Print("\r\n"), *color, Print(status), ResetColor)?;
+                max_results_shown = max_results_shown.saturating_sub(1);
+            }
+            // the status bar is always shown below the results
+            if config.status_bar {
+                max_results_shown = max_results_shown.saturating_sub(1);
+            }
+
+            // the preview pane, if shown, takes a part of the list area
+            let layout = config.preview.as_ref().and_then(|preview| {
+                let layouts = &preview.layouts;
+                layouts.get(*preview_layout % layouts.len().max(1))
+            });
+            let preview = state.preview().zip(layout.copied());
+            let (list_rows, preview_rows, preview_width) = match preview {
+                Some((_, layout)) if layout.position == PreviewPosition::Right => {
+                    let preview_width = term_width * usize::from(layout.size.min(100)) / 100;
+                    (max_results_shown, max_results_shown, preview_width)
+                }
+                Some((_, layout)) => {
+                    let preview_rows = max_results_shown * usize::from(layout.size.min(100)) / 100;
+                    (max_results_shown - preview_rows, preview_rows, term_width)
+                }
+                None => (max_results_shown, 0, 0),
+            };
+            let right_preview =
+                preview.is_some_and(|(_, layout)| layout.position == PreviewPosition::Right);
+            *page_size = list_rows.max(1);
+            let num_results = state.num_rows();
+            // the scrollbar is only shown if the results don't fit
+            let scrollbar = config.scrollbar && list_rows > 0 && num_results > list_rows;
+            let new_list_width = if right_preview {
+                // one column is taken by the separator
+                term_width.saturating_sub(preview_width + 1)
+            } else {
+                term_width
+            }
+            .saturating_sub(usize::from(scrollbar));
+            if new_list_width != *list_width {
+                row_cache.invalidate();
+                *list_width = new_list_width;
+            }
+            let list_width = new_list_width;
+            // the selected row is prefixed by the pointer, marked entries by a
+            // marker in multi-select mode, and pinned entries by another one
+            let pointer_width = config.pointer.width();
+            let marker_width = if config.multi_select || config.pinning {
+                config.marker.width().max(PINNED_MARKER.width())
+            } else {
+                0
+            };
+            let row_width = list_width.saturating_sub(pointer_width + marker_width);
+            let preview_lines = preview.map_or_else(Vec::new, |(content, _)| {
+                content
+                    .lines()
+                    .take(preview_rows)
+                    .map(|line| truncate_to_width(&config.ansi.clean(line), preview_width).0)
+                    .collect()
+            });
+
+            if num_results == 0 {
+                rows += 1;
+                queue!(out, Print("\r\nno entries found"))?;
+            } else {
+                let num_results_shown = num_results.min(list_rows);
+                *scroll_offset = clamp_scroll_offset(
+                    *scroll_offset,
+                    selected_index,
+                    num_results,
+                    num_results_shown,
+                );
+                let num_rows = if right_preview {
+                    list_rows
+                } else {
+                    num_results_shown
+                };
+                // the rows of the scrollbar covered by its thumb
+                let thumb = if scrollbar {
+                    let top = match config.navigation_mode {
+                        NavigationMode::Fixed => *scroll_offset,
+                        NavigationMode::Rotate => selected_index,
+                    };
+                    let size = (list_rows * list_rows / num_results).max(1);
+                    // the thumb reaches the bottom when the last result is shown
+                    let start = (top.min(num_results - list_rows) * (list_rows - size))
+                        / (num_results - list_rows);
+                    start..start + size
+                } else {
+                    0..0
+                };
+                for index in 0..num_rows {
+                    rows += 1;
+                    queue!(out, Print("\r\n"))?;
+                    if index >= num_results_shown {
+                        clicked_rows.push(None);
+                    } else {
+                        let (row_index, selected) = match config.navigation_mode {
+                            NavigationMode::Fixed => {
+                                let row_index = *scroll_offset + index;
+                                (row_index, row_index == selected_index)
+                            }
+                            NavigationMode::Rotate => {
+                                ((selected_index + index) % num_results, index == 0)
+                            }
+                        };
+                        // the selection may still point past the end of a list
+                        // that just shrank
+                        let Some(row) = state.row(row_index) else {
+                            clicked_rows.push(None);
+                            continue;
+                        };
+                        clicked_rows.push(Some(row_index));
+                        let stale = config.dim_stale_results && state.results_stale();
+                        let row_fg = if selected {
+                            theme.selected_fg.0
+                        } else {
+                            theme.text.0
+                        };
+                        // stale results may have their own color instead of being dimmed
+                        let text_fg = match theme.stale {
+                            Some(color) if stale => color.0,
+                            _ => row_fg,
+                        };
+                        let mut row_style = String::new();
+                        let mut text_style = String::new();
+                        SetForegroundColor(colors.map(row_fg)).write_ansi(&mut row_style)?;
+                        SetForegroundColor(colors.map(text_fg)).write_ansi(&mut text_style)?;
+                        let dim = stale && (theme.stale.is_none() || !colors.enabled());
+                        if selected && colors.enabled() {
+                            let bg = SetBackgroundColor(colors.map(theme.selected_bg.0));
+                            bg.write_ansi(&mut row_style)?;
+                            bg.write_ansi(&mut text_style)?;
+                        } else if selected {
+                            // without colors, the selected row is shown in bold reverse video
+                            SetAttribute(Attribute::Reverse).write_ansi(&mut row_style)?;
+                            SetAttribute(Attribute::Bold).write_ansi(&mut row_style)?;
+                            SetAttribute(Attribute::Reverse).write_ansi(&mut text_style)?;
+                            if !dim {
+                                SetAttribute(Attribute::Bold).write_ansi(&mut text_style)?;
+                            }
+                        }
+                        if dim {
+                            SetAttribute(Attribute::Dim).write_ansi(&mut text_style)?;
+                        }
+                        queue!(out, Print(&row_style), Clear(ClearType::UntilNewLine))?;
+                        let pointer = if selected {
+                            config.pointer.as_str()
+                        } else {
+                            ""
+                        };
+                        let marker = match &row {
+                            Row::Entry(_, entry) if state.is_marked(entry) => {
+                                config.marker.as_str()
+                            }
+                            Row::Entry(_, entry) if state.is_pinned(entry) => PINNED_MARKER,
+                            _ => "",
+                        };
+                        let prefix = format!(
+                            "{}{}",
+                            pad_to_width(pointer, pointer_width),
+                            pad_to_width(marker, marker_width)
+                        );
+                        let (prefix, _) = truncate_to_width(&prefix, list_width);
+                        queue!(out, Print(prefix))?;
+                        let width = match row {
+                            Row::Entry(entry_index, entry) => {
+                                let row = row_cache.get_or_render(
+                                    entry,
+                                    entry_index,
+                                    index,
+                                    row_width,
+                                    || {
+                                        display_template.render(&Context {
+                                            identifier: &config.ansi.clean(&entry.identifier),
+                                            title: &config.ansi.clean(&entry.title),
+                                            confidence: entry.confidence,
+                                            index: entry_index,
+                                            one_based_index: entry_index + 1,
+                                            display_index: index,
+                                            one_based_display_index: index + 1,
+                                            extra: config.ansi.clean_map(&entry.extra),
+                                        })
+                                    },
+                                )?;
+                                let text = if config.highlight_matches {
+                                    let match_fg = if selected {
+                                        theme.selected_matched.0
+                                    } else {
+                                        theme.matched.0
+                                    };
+                                    let mut start = String::new();
+                                    let mut end = String::new();
+                                    if colors.enabled() {
+                                        SetForegroundColor(colors.map(match_fg))
+                                            .write_ansi(&mut start)?;
+                                        SetForegroundColor(colors.map(text_fg))
+                                            .write_ansi(&mut end)?;
+                                    } else {
+                                        SetAttribute(Attribute::Underlined)
+                                            .write_ansi(&mut start)?;
+                                        SetAttribute(Attribute::NoUnderline)
+                                            .write_ansi(&mut end)?;
+                                    }
+                                    highlight_matches(&row.text, state.query(), &start, &end)
+                                } else {
+                                    Cow::Borrowed(row.text.as_str())
+                                };
+                                // rendered escape sequences may reset the style of the
+                                // row, which is applied again after them
+                                queue!(
+                                    out,
+                                    Print(&text_style),
+                                    Print(reapply_after_resets(&text, &text_style))
+                                )?;
+                                if dim {
+                                    queue!(out, SetAttribute(Attribute::NormalIntensity))?;
+                                }
+                                queue!(out, Print(&row_style))?;
+                                row.width
+                            }
+                            Row::Create => {
+                                #[derive(Serialize)]
+                                struct CreateContext<'a> {
+                                    query: &'a str,
+                                }
+
+                                let title = match create_template {
+                                    Some(template) => template.render(&CreateContext {
+                                        query: state.query(),
+                                    })?,
+                                    None => String::new(),
+                                };
+                                let (title, width) =
+                                    truncate_with_ellipsis(&sanitize(&title), row_width);
+                                queue!(
+                                    out,
+                                    SetAttribute(Attribute::Italic),
+                                    Print(title),
+                                    SetAttribute(Attribute::NoItalic)
+                                )?;
+                                width
+                            }
+                            Row::Collapsed(source) => {
+                                let label = format!(
+                                    "▸ {} ({} hidden)",
+                                    config.ansi.clean(&source.name),
+                                    source.count
+                                );
+                                let (label, width) =
+                                    truncate_with_ellipsis(&sanitize(&label), row_width);
+                                queue!(
+                                    out,
+                                    SetAttribute(Attribute::Italic),
+                                    Print(label),
+                                    SetAttribute(Attribute::NoItalic)
+                                )?;
+                                width
+                            }
+                            Row::Suggestion(query) => {
+                                let hint = format!("did you mean: {}", config.ansi.clean(query));
+                                let (hint, width) = truncate_with_ellipsis(&hint, row_width);
+                                queue!(
+                                    out,
+                                    SetAttribute(Attribute::Italic),
+                                    Print(hint),
+                                    SetAttribute(Attribute::NoItalic)
+                                )?;
+                                width
+                            }
+                        };
+                        if config.ansi == AnsiMode::Render {
+                            queue!(out, SetAttribute(Attribute::Reset), Print(&row_style))?;
+                        }
+                        if selected {
+                            // pad the highlighted row so the selection bar spans the whole list
+                            queue!(out, Print(" ".repeat(row_width - width)))?;
+                        }
+                        queue!(out, SetAttribute(Attribute::Reset))?;
+                    }
+                    if scrollbar {
+                        queue!(
+                            out,
+                            MoveToColumn(list_width as u16),
+                            Print(if thumb.contains(&index) { '┃' } else { '│' })
+                        )?;
+                    }
+                    if right_preview {
+                        let line = preview_lines.get(index).map_or("", String::as_str);
+                        queue!(
+                            out,
+                            MoveToColumn((list_width + usize::from(scrollbar)) as u16),
+                            Print('│'),
+                            Print(line)
+                        )?;
+                        if config.ansi == AnsiMode::Render {
+                            queue!(out, SetAttribute(Attribute::Reset))?;
+                        }
+                    }
+                }
+
+                if preview.is_some() && !right_preview && preview_rows > 0 {
+                    rows += 1;
+                    queue!(out, Print("\r\n"), Print("─".repeat(term_width)))?;
+                    for line in preview_lines.iter().take(preview_rows - 1) {
+                        rows += 1;
+                        queue!(out, Print("\r\n"), Print(line))?;
+                        if config.ansi == AnsiMode::Render {
+                            queue!(out, SetAttribute(Attribute::Reset))?;
+                        }
+                    }
+                }
+            }
+
+            if let Some((status, color)) = status.filter(|_| config.status_bar) {
+                rows += 1;
+                queue!(out, Print("\r\n"), color, Print(status), ResetColor)?;
+            }
+        }
+
+        Ok(rows)
+    }
+}
+
+/// keeps the selected row among the `num_results_shown` rows from the scroll
+/// offset, without scrolling past the end of the list
+fn clamp_scroll_offset(
+    scroll_offset: usize,
+    selected_index: usize,
+    num_results: usize,
+    num_results_shown: usize,
+) -> usize {
+    scroll_offset
+        .min(selected_index)
+        .max((selected_index + 1).saturating_sub(num_results_shown))
+        .min(num_results.saturating_sub(num_results_shown))
+}
+
+/// truncates or pads `text` with spaces so that it is exactly `width` cells wide
+fn pad_to_width(text: &str, width: usize) -> String {
+    let (mut text, text_width) = truncate_to_width(text, width);
+    text.extend(std::iter::repeat_n(' ', width - text_width));
+    text
+}
+
+/// the template variables of the prompt and the header
+#[derive(Serialize)]
+struct PromptContext<'a> {
+    name: &'a str,
+    count: Option<usize>,
+    path: &'a str,
+}
+
+impl<'a> PromptContext<'a> {
+    fn new(config: &'a Config, state: &'a State) -> Self {
+        Self {
+            name: &config.name,
+            count: state.result().map(|result| result.results.len()),
+            path: state.path(),
+        }
+    }
+}
+
+/// the rendered rows by identifier, reused while the entry and the width don't
+/// change. rows are only rendered once they become visible
+struct RowCache {
+    rows: HashMap<String, CachedRow>,
+    /// the rows rendered for the previous results, moved back to `rows` if
+    /// they are still shown, so that the rows of entries that are gone are
+    /// eventually dropped
+    previous: HashMap<String, CachedRow>,
+    /// whether the display template depends on the position of an entry, in
+    /// which case a cached row is only valid at the same position
+    position_dependent: bool,
+}
+
+struct CachedRow {
+    /// the entry the row was rendered for
+    entry: SearchResultEntry,
+    entry_index: usize,
+    display_index: usize,
+    text: String,
+    width: usize,
+}
+
+impl RowCache {
+    fn new(display_template: &str) -> Self {
+        Self {
+            rows: HashMap::new(),
+            previous: HashMap::new(),
+            // also covers `display_index`
+            position_dependent: display_template.contains("index"),
+        }
+    }
+
+    /// drops every cached row, e.g. when the terminal width changes
+    fn invalidate(&mut self) {
+        self.rows.clear();
+        self.previous.clear();
+    }
+
+    /// keeps the rows of the previous results, which are reused for the
+    /// entries that didn't change
+    fn results_changed(&mut self) {
+        self.previous = std::mem::take(&mut self.rows);
+    }
+
+    fn get_or_render(
+        &mut self,
+        entry: &SearchResultEntry,
+        entry_index: usize,
+        display_index: usize,
+        max_width: usize,
+        render: impl FnOnce() -> anyhow::Result<String>,
+    ) -> anyhow::Result<&CachedRow> {
+        if let Some(row) = self.previous.remove(&entry.identifier) {
+            self.rows.insert(entry.identifier.clone(), row);
+        }
+        let cached = self.rows.get(&entry.identifier).is_some_and(|row| {
+            row.entry == *entry
+                && !(self.position_dependent
+                    && (row.entry_index, row.display_index) != (entry_index, display_index))
+        });
+        if !cached {
+            let (text, width) = truncate_with_ellipsis(&render()?, max_width);
+            let row = CachedRow {
+                entry: entry.clone(),
+                entry_index,
+                display_index,
+                text,
+                width,
+            };
+            self.rows.insert(entry.identifier.clone(), row);
+        }
+
+        Ok(&self.rows[&entry.identifier])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::clamp_scroll_offset;
+
+    #[test]
+    fn scroll_offset_follows_the_selection() {
+        assert_eq!(clamp_scroll_offset(0, 25, 100, 20), 6);
+        assert_eq!(clamp_scroll_offset(30, 25, 100, 20), 25);
+        assert_eq!(clamp_scroll_offset(10, 15, 100, 20), 10);
+    }
+
+    #[test]
+    fn growing_terminal_does_not_scroll_past_the_end() {
+        // the last of 100 results was selected with 20 rows
+        let offset = clamp_scroll_offset(0, 99, 100, 20);
+        assert_eq!(offset, 80);
+        let offset = clamp_scroll_offset(offset, 99, 100, 38);
+        assert_eq!(offset, 62);
+        assert!(offset + 38 <= 100);
+    }
+
+    #[test]
+    fn shrinking_list_does_not_scroll_past_the_end() {
+        // a refresh leaves 30 results while the 90th is still selected
+        let offset = clamp_scroll_offset(80, 89, 30, 20);
+        assert_eq!(offset, 10);
+        assert_eq!(clamp_scroll_offset(5, 89, 3, 3), 0);
+    }
+}
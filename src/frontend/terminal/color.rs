@@ -0,0 +1,162 @@
+//! detecting how many colors the terminal supports, and mapping colors down
+//! to what it can display. colors are disabled if `NO_COLOR` is set
+
+use std::{env, process::Command};
+
+use crossterm::style::Color;
+
+use crate::ColorDepth;
+
+/// the 16 basic colors with their usual (xterm) values, in palette order
+const BASIC_COLORS: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (205, 0, 0)),
+    (Color::DarkGreen, (0, 205, 0)),
+    (Color::DarkYellow, (205, 205, 0)),
+    (Color::DarkBlue, (0, 0, 238)),
+    (Color::DarkMagenta, (205, 0, 205)),
+    (Color::DarkCyan, (0, 205, 205)),
+    (Color::Grey, (229, 229, 229)),
+    (Color::DarkGrey, (127, 127, 127)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (92, 92, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// the levels of the 6x6x6 color cube of the 256-color palette
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+#[derive(Clone, Copy)]
+pub struct Colors {
+    depth: ColorDepth,
+    /// whether `NO_COLOR` is set, see <https://no-color.org>
+    disabled: bool,
+}
+
+impl Colors {
+    pub fn new(depth: ColorDepth) -> Self {
+        Self {
+            depth: match depth {
+                ColorDepth::Auto => detect(),
+                depth => depth,
+            },
+            disabled: env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()),
+        }
+    }
+
+    /// whether colors are shown at all, attributes like reverse video have to
+    /// be used instead if not
+    pub fn enabled(self) -> bool {
+        !self.disabled
+    }
+
+    /// maps `color` to the closest one the terminal can display
+    pub fn map(self, color: Color) -> Color {
+        if self.disabled {
+            return Color::Reset;
+        }
+        match (self.depth, color) {
+            (ColorDepth::Auto | ColorDepth::TrueColor, color) => color,
+            (ColorDepth::Ansi256, Color::Rgb { r, g, b }) => {
+                Color::AnsiValue(to_ansi256((r, g, b)))
+            }
+            (ColorDepth::Ansi16, Color::Rgb { r, g, b }) => nearest_basic((r, g, b)),
+            (ColorDepth::Ansi16, Color::AnsiValue(value)) => {
+                match BASIC_COLORS.get(usize::from(value)) {
+                    Some((color, _)) => *color,
+                    None => nearest_basic(ansi256_rgb(value)),
+                }
+            }
+            (_, color) => color,
+        }
+    }
+}
+
+/// guesses the color depth from `COLORTERM`, `TERM` and terminfo
+fn detect() -> ColorDepth {
+    let colorterm = env::var("COLORTERM").unwrap_or_default();
+    if colorterm == "truecolor" || colorterm == "24bit" {
+        return ColorDepth::TrueColor;
+    }
+
+    let term = env::var("TERM").unwrap_or_default();
+    if term.contains("truecolor") || term.contains("direct") {
+        return ColorDepth::TrueColor;
+    }
+    if term.contains("256color") {
+        return ColorDepth::Ansi256;
+    }
+
+    // the number of colors in the terminfo entry, if tput is available
+    let terminfo_colors = Command::new("tput")
+        .arg("colors")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .trim()
+                .parse::<u32>()
+                .ok()
+        });
+    match terminfo_colors {
+        Some(colors) if colors >= 1 << 24 => ColorDepth::TrueColor,
+        Some(colors) if colors >= 256 => ColorDepth::Ansi256,
+        _ => ColorDepth::Ansi16,
+    }
+}
+
+fn to_ansi256((r, g, b): (u8, u8, u8)) -> u8 {
+    let level = |value: u8| {
+        CUBE_LEVELS
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, level)| level.abs_diff(value))
+            .map_or(0, |(index, _)| index as u8)
+    };
+    let cube_index = 16 + 36 * level(r) + 6 * level(g) + level(b);
+
+    // grays are often better matched by the grayscale ramp
+    let average = ((u16::from(r) + u16::from(g) + u16::from(b)) / 3) as u8;
+    let gray_index = 232 + (average.saturating_sub(8) / 10).min(23);
+    if distance(ansi256_rgb(gray_index), (r, g, b)) < distance(ansi256_rgb(cube_index), (r, g, b)) {
+        gray_index
+    } else {
+        cube_index
+    }
+}
+
+/// the usual value of a color of the 256-color palette
+fn ansi256_rgb(value: u8) -> (u8, u8, u8) {
+    match value {
+        0..=15 => BASIC_COLORS[usize::from(value)].1,
+        16..=231 => {
+            let index = value - 16;
+            (
+                CUBE_LEVELS[usize::from(index / 36)],
+                CUBE_LEVELS[usize::from(index / 6 % 6)],
+                CUBE_LEVELS[usize::from(index % 6)],
+            )
+        }
+        232..=255 => {
+            let gray = 8 + 10 * (value - 232);
+            (gray, gray, gray)
+        }
+    }
+}
+
+fn nearest_basic(rgb: (u8, u8, u8)) -> Color {
+    BASIC_COLORS
+        .iter()
+        .min_by_key(|(_, basic)| distance(*basic, rgb))
+        .map_or(Color::Reset, |(color, _)| *color)
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let square = |x: u8, y: u8| u32::from(x.abs_diff(y)).pow(2);
+    square(a.0, b.0) + square(a.1, b.1) + square(a.2, b.2)
+}
@@ -0,0 +1,87 @@
+//! moving the cursor back to the end of the query after drawing below it
+
+use std::{env, io::Write};
+
+use crossterm::{
+    cursor::{position, MoveTo, RestorePosition, SavePosition},
+    queue,
+    terminal::size,
+};
+
+use crate::CursorPositioning;
+
+pub enum Cursor {
+    /// the DEC save/restore cursor sequences
+    SaveRestore,
+    /// absolute positioning, tracking the row of the query line as the
+    /// terminal scrolls
+    Absolute { row: u16, column: u16 },
+}
+
+impl Cursor {
+    /// must be called while the cursor is on the query line
+    pub fn new(positioning: CursorPositioning) -> anyhow::Result<Self> {
+        Ok(match positioning {
+            // terminals that don't report the cursor position get save/restore
+            CursorPositioning::Auto if save_restore_unreliable() => match position() {
+                Ok((column, row)) => Cursor::Absolute { row, column },
+                Err(_) => Cursor::SaveRestore,
+            },
+            CursorPositioning::Auto | CursorPositioning::SaveRestore => Cursor::SaveRestore,
+            CursorPositioning::Absolute => {
+                let (column, row) = position()?;
+                Cursor::Absolute { row, column }
+            }
+        })
+    }
+
+    /// remembers the current cursor position, which is at `column` on the
+    /// query line
+    pub fn save(&mut self, out: &mut impl Write, column: usize) -> anyhow::Result<()> {
+        match self {
+            Cursor::SaveRestore => queue!(out, SavePosition)?,
+            Cursor::Absolute { column: saved, .. } => {
+                *saved = column.try_into().unwrap_or(u16::MAX);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn restore(&self, out: &mut impl Write) -> anyhow::Result<()> {
+        match self {
+            Cursor::SaveRestore => queue!(out, RestorePosition)?,
+            Cursor::Absolute { row, column } => queue!(out, MoveTo(*column, *row))?,
+        }
+        Ok(())
+    }
+
+    /// the row of the query line, if it is known
+    pub fn row(&self) -> Option<u16> {
+        match self {
+            Cursor::SaveRestore => None,
+            Cursor::Absolute { row, .. } => Some(*row),
+        }
+    }
+
+    /// accounts for `rows` lines printed below the query line, which scroll
+    /// the terminal if they don't fit below it
+    pub fn printed_rows(&mut self, rows: usize) -> anyhow::Result<()> {
+        if let Cursor::Absolute { row, .. } = self {
+            let term_height = usize::from(size()?.1);
+            let max_row = term_height.saturating_sub(rows + 1);
+            *row = (*row).min(max_row.try_into().unwrap_or(u16::MAX));
+        }
+        Ok(())
+    }
+}
+
+/// terminal multiplexers (and the serial consoles they often run on) don't
+/// always keep the position saved by DEC save cursor, e.g. when they redraw the
+/// pane
+fn save_restore_unreliable() -> bool {
+    let term = env::var("TERM").unwrap_or_default();
+    env::var_os("TMUX").is_some()
+        || env::var_os("STY").is_some()
+        || term.starts_with("screen")
+        || term.starts_with("tmux")
+}
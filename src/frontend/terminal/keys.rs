@@ -0,0 +1,166 @@
+//! key names used in the config, like `ctrl+f` or `alt+enter`
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::{action::CopyField, state::Input};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl Key {
+    pub fn parse(name: &str) -> anyhow::Result<Self> {
+        let invalid = || anyhow::anyhow!("invalid key {name:?}");
+        let mut parts: Vec<&str> = name.split('+').collect();
+        // `+` and `ctrl++` name the plus key
+        if name.ends_with("++") || name == "+" {
+            parts.pop();
+            *parts.last_mut().ok_or_else(invalid)? = "+";
+        }
+        let (key, modifier_names) = parts.split_last().ok_or_else(invalid)?;
+
+        let mut modifiers = KeyModifiers::NONE;
+        for modifier in modifier_names {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(invalid()),
+            };
+        }
+
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match key.to_lowercase().as_str() {
+                "enter" | "return" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "space" => KeyCode::Char(' '),
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                name => name
+                    .strip_prefix('f')
+                    .and_then(|number| number.parse().ok())
+                    .filter(|number| (1..=24).contains(number))
+                    .map(KeyCode::F)
+                    .ok_or_else(invalid)?,
+            },
+        };
+        Ok(Self::new(code, modifiers))
+    }
+
+    fn new(code: KeyCode, mut modifiers: KeyModifiers) -> Self {
+        // shift is already part of the character, e.g. `A` for shift+a
+        let code = match code {
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::Char(c.to_uppercase().next().unwrap_or(c))
+            }
+            code => code,
+        };
+        Self { code, modifiers }
+    }
+
+    pub fn matches(&self, event: &KeyEvent) -> bool {
+        *self == Self::new(event.code, event.modifiers)
+    }
+}
+
+/// the picker actions that keys can be bound to with `keybindings`
+#[derive(Deserialize, JsonSchema, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyAction {
+    Accept,
+    Cancel,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    #[serde(alias = "toggle_select")]
+    ToggleMark,
+    TogglePin,
+    ToggleCollapse,
+    ToggleSort,
+    TogglePreview,
+    RefreshPreview,
+    ToggleHistory,
+    RecallPrevious,
+    RecallNext,
+    /// runs the current search again
+    #[serde(alias = "refresh")]
+    Reload,
+    CopyIdentifier,
+    CopyTitle,
+    CopyJson,
+    CursorLeft,
+    CursorRight,
+    WordLeft,
+    WordRight,
+    CursorStart,
+    CursorEnd,
+    DeleteBackward,
+    DeleteForward,
+    DeleteWordBackward,
+    KillToStart,
+    KillToEnd,
+    Yank,
+    YankPop,
+    Undo,
+    Redo,
+}
+
+impl KeyAction {
+    pub fn input(self, page_size: usize) -> Input {
+        match self {
+            KeyAction::Accept => Input::Accept,
+            KeyAction::Cancel => Input::Cancel,
+            KeyAction::Up => Input::SelectPrevious,
+            KeyAction::Down => Input::SelectNext,
+            KeyAction::PageUp => Input::SelectPreviousPage(page_size),
+            KeyAction::PageDown => Input::SelectNextPage(page_size),
+            KeyAction::ToggleMark => Input::ToggleMark,
+            KeyAction::TogglePin => Input::TogglePin,
+            KeyAction::ToggleCollapse => Input::ToggleCollapse,
+            KeyAction::ToggleSort => Input::ToggleSort,
+            KeyAction::TogglePreview => Input::TogglePreview,
+            KeyAction::RefreshPreview => Input::RefreshPreview,
+            KeyAction::ToggleHistory => Input::ToggleHistory,
+            KeyAction::RecallPrevious => Input::RecallPrevious,
+            KeyAction::RecallNext => Input::RecallNext,
+            KeyAction::Reload => Input::Refresh,
+            KeyAction::CopyIdentifier => Input::Copy(CopyField::Identifier),
+            KeyAction::CopyTitle => Input::Copy(CopyField::Title),
+            KeyAction::CopyJson => Input::Copy(CopyField::Json),
+            KeyAction::CursorLeft => Input::MoveCursorLeft,
+            KeyAction::CursorRight => Input::MoveCursorRight,
+            KeyAction::WordLeft => Input::MoveWordLeft,
+            KeyAction::WordRight => Input::MoveWordRight,
+            KeyAction::CursorStart => Input::MoveCursorStart,
+            KeyAction::CursorEnd => Input::MoveCursorEnd,
+            KeyAction::DeleteBackward => Input::DeleteBackward,
+            KeyAction::DeleteForward => Input::DeleteForward,
+            KeyAction::DeleteWordBackward => Input::DeleteWordBackward,
+            KeyAction::KillToStart => Input::KillToStart,
+            KeyAction::KillToEnd => Input::KillToEnd,
+            KeyAction::Yank => Input::Yank,
+            KeyAction::YankPop => Input::YankPop,
+            KeyAction::Undo => Input::Undo,
+            KeyAction::Redo => Input::Redo,
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! state that is kept between runs, stored in `$XDG_STATE_HOME/search-tui`
+
+use std::{
+    collections::HashMap,
+    env,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// the number of past queries kept per profile
+const MAX_QUERIES: usize = 1000;
+/// the number of accepted entries kept per profile
+const MAX_ENTRIES: usize = 1000;
+
+fn state_dir() -> anyhow::Result<PathBuf> {
+    env::var_os("XDG_STATE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .map(|dir| dir.join("search-tui"))
+        .context("unable to determine the state directory")
+}
+
+/// loads a state file, which is empty if it doesn't exist yet
+fn load<T: DeserializeOwned + Default>(file_name: &str) -> anyhow::Result<T> {
+    let path = state_dir()?.join(file_name);
+    match fs::read(&path) {
+        Ok(content) => serde_json::from_slice(&content)
+            .with_context(|| format!("unable to parse {}", path.display())),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(T::default()),
+        Err(err) => Err(err).with_context(|| format!("unable to read {}", path.display())),
+    }
+}
+
+fn save<T: Serialize>(file_name: &str, state: &T) -> anyhow::Result<()> {
+    let dir = state_dir()?;
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(file_name);
+    fs::write(&path, serde_json::to_vec(state)?)
+        .with_context(|| format!("unable to write {}", path.display()))
+}
+
+/// the last query of every profile, keyed by profile name
+type LastQueries = HashMap<String, String>;
+
+pub fn load_last_query(profile: &str) -> anyhow::Result<Option<String>> {
+    Ok(load::<LastQueries>("last_queries.json")?.remove(profile))
+}
+
+pub fn save_last_query(profile: &str, query: &str) -> anyhow::Result<()> {
+    let mut last_queries: LastQueries = load("last_queries.json")?;
+    last_queries.insert(profile.to_string(), query.to_string());
+    save("last_queries.json", &last_queries)
+}
+
+/// the identifiers of the pinned entries of every profile, keyed by profile
+/// name
+type PinnedEntries = HashMap<String, Vec<String>>;
+
+pub fn load_pinned(profile: &str) -> anyhow::Result<Vec<String>> {
+    Ok(load::<PinnedEntries>("pinned.json")?
+        .remove(profile)
+        .unwrap_or_default())
+}
+
+pub fn save_pinned(profile: &str, identifiers: &[String]) -> anyhow::Result<()> {
+    let mut pinned: PinnedEntries = load("pinned.json")?;
+    pinned.insert(profile.to_string(), identifiers.to_vec());
+    save("pinned.json", &pinned)
+}
+
+/// the past queries of every profile, keyed by profile name
+type QueryHistory = HashMap<String, Vec<PastQuery>>;
+
+#[derive(Serialize, Deserialize)]
+struct PastQuery {
+    query: String,
+    #[serde(flatten)]
+    usage: Usage,
+}
+
+/// the accepted entries of every profile, keyed by profile name
+type EntryHistory = HashMap<String, Vec<PastEntry>>;
+
+#[derive(Serialize, Deserialize)]
+struct PastEntry {
+    identifier: String,
+    #[serde(flatten)]
+    usage: Usage,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Usage {
+    /// the number of times the query or entry was used
+    count: u32,
+    /// when it was last used, in seconds since the unix epoch
+    last_used: u64,
+}
+
+impl Usage {
+    fn new() -> Self {
+        Self {
+            count: 1,
+            last_used: now(),
+        }
+    }
+
+    fn record(&mut self) {
+        self.count += 1;
+        self.last_used = now();
+    }
+
+    /// frequently and recently used queries and entries score higher, with
+    /// the weight of a use halving every week
+    fn frecency(&self, now: u64) -> f64 {
+        const HALF_LIFE_SECS: f64 = 7.0 * 24.0 * 60.0 * 60.0;
+        let age = now.saturating_sub(self.last_used) as f64;
+        f64::from(self.count) * 0.5f64.powf(age / HALF_LIFE_SECS)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// the past queries of `profile`, most frecent first
+pub fn load_queries(profile: &str) -> anyhow::Result<Vec<String>> {
+    let mut queries = load::<QueryHistory>("query_history.json")?
+        .remove(profile)
+        .unwrap_or_default();
+    sort_by_frecency(&mut queries, |past| &past.usage);
+    Ok(queries.into_iter().map(|past| past.query).collect())
+}
+
+pub fn add_query(profile: &str, query: &str) -> anyhow::Result<()> {
+    let mut history: QueryHistory = load("query_history.json")?;
+    let queries = history.entry(profile.to_string()).or_default();
+    match queries.iter_mut().find(|past| past.query == query) {
+        Some(past) => past.usage.record(),
+        None => queries.push(PastQuery {
+            query: query.to_string(),
+            usage: Usage::new(),
+        }),
+    }
+    sort_by_frecency(queries, |past| &past.usage);
+    queries.truncate(MAX_QUERIES);
+    save("query_history.json", &history)
+}
+
+/// the frecency of the accepted entries of `profile`, by identifier
+pub fn load_entry_frecencies(profile: &str) -> anyhow::Result<HashMap<String, f64>> {
+    let now = now();
+    Ok(load::<EntryHistory>("entry_history.json")?
+        .remove(profile)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|past| (past.identifier, past.usage.frecency(now)))
+        .collect())
+}
+
+/// records that the entries with `identifiers` were accepted
+pub fn add_entries<'a>(
+    profile: &str,
+    identifiers: impl IntoIterator<Item = &'a str>,
+) -> anyhow::Result<()> {
+    let mut history: EntryHistory = load("entry_history.json")?;
+    let entries = history.entry(profile.to_string()).or_default();
+    for identifier in identifiers {
+        match entries
+            .iter_mut()
+            .find(|past| past.identifier == identifier)
+        {
+            Some(past) => past.usage.record(),
+            None => entries.push(PastEntry {
+                identifier: identifier.to_string(),
+                usage: Usage::new(),
+            }),
+        }
+    }
+    sort_by_frecency(entries, |past| &past.usage);
+    entries.truncate(MAX_ENTRIES);
+    save("entry_history.json", &history)
+}
+
+fn sort_by_frecency<T>(items: &mut [T], usage: impl Fn(&T) -> &Usage) {
+    let now = now();
+    items.sort_by(|a, b| usage(b).frecency(now).total_cmp(&usage(a).frecency(now)));
+}
+
+/// an accepted entry, as recorded in the selection log
+#[derive(Serialize)]
+pub struct Selection<'a> {
+    pub profile: &'a str,
+    pub query: &'a str,
+    pub identifier: &'a str,
+    /// the position of the entry in the shown results, starting from 1
+    pub rank: Option<usize>,
+}
+
+/// appends `selection` to the selection log, one json object per line
+pub fn log_selection(selection: &Selection) -> anyhow::Result<()> {
+    #[derive(Serialize)]
+    struct Record<'a> {
+        /// in seconds since the unix epoch
+        timestamp: u64,
+        #[serde(flatten)]
+        selection: &'a Selection<'a>,
+    }
+
+    let dir = state_dir()?;
+    fs::create_dir_all(&dir)?;
+    let path = dir.join("selections.jsonl");
+    let mut line = serde_json::to_vec(&Record {
+        timestamp: now(),
+        selection,
+    })?;
+    line.push(b'\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(&line))
+        .with_context(|| format!("unable to write {}", path.display()))
+}
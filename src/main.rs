@@ -1,303 +1,1140 @@
 #![feature(exit_status_error)]
 
+mod action;
+mod cache;
+mod check;
+mod cli;
+mod filter;
+mod frontend;
+mod history;
+mod preview;
+mod source;
+mod state;
+mod template;
+mod text;
+mod theme;
+
 use std::{
-    io::{stdin, stdout, Stdout},
-    process::Command,
-    time::Duration,
+    borrow::Cow,
+    collections::BTreeMap,
+    env,
+    fs::File,
+    io::{self, stdin, BufRead, BufReader, IsTerminal},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant},
 };
 
+use action::{Action, SilentAction};
 use anyhow::Context;
-use crossterm::{
-    cursor::{MoveRight, RestorePosition, SavePosition},
-    event::{Event, EventStream, KeyCode},
-    execute, queue,
-    style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor},
-    terminal::{disable_raw_mode, enable_raw_mode, size, Clear, ClearType},
+use cache::{CacheConfig, ResultCache};
+use clap::{Parser, ValueEnum};
+use cli::Cli;
+use frontend::{Change, Frontend, FrontendEvent, KeyAction, TerminalFrontend};
+use futures::{
+    channel::mpsc,
+    future::{self, Fuse},
+    pin_mut,
+    stream::{self, FuturesUnordered},
+    FutureExt, Stream, StreamExt,
 };
-use futures::{future::Fuse, pin_mut, FutureExt, StreamExt};
-use serde::{Deserialize, Serialize};
-use tinytemplate::TinyTemplate;
-
-const QUERY_PREFIX: &str = "Search > ";
+use preview::Preview;
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+use source::Source;
+use state::{Effect, Input, State};
+use template::Template;
+use text::{sanitize, sanitize_keeping_sgr, strip_ansi};
+use theme::Theme;
+use tokio::time::{Interval, MissedTickBehavior};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
+#[schemars(transform = extend_config_schema)]
 struct Config {
-    query_command: QueryCommand,
+    #[serde(alias = "query_command")]
+    source: Source,
+    /// how long to wait after the query changes before searching
+    #[serde(default = "default_timeout_millis")]
     timeout_millis: u64,
+    /// the longest a search is delayed while the query keeps changing, so
+    /// that the results are updated while typing
+    max_wait_millis: Option<u64>,
+    #[serde(
+        default = "default_display_template",
+        deserialize_with = "template::deserialize_expanded"
+    )]
     display_template: String,
+    /// the name of this search mode, available to the prompt as `{name}`
+    #[serde(default)]
+    name: String,
+    preview: Option<Preview>,
+    /// the prompt template, overridden by `--prompt`
+    #[serde(
+        default = "default_prompt",
+        deserialize_with = "template::deserialize_expanded"
+    )]
+    prompt: String,
+    /// shown dimmed in place of the query while it is empty
+    placeholder: Option<String>,
+    /// a template shown between the query and the results, with the same
+    /// template variables as the prompt
+    #[serde(default, deserialize_with = "template::deserialize_expanded_option")]
+    header: Option<String>,
+    #[serde(default)]
+    navigation_mode: NavigationMode,
+    /// shows a scrollbar next to the results if they don't fit
+    #[serde(default)]
+    scrollbar: bool,
+    /// clicking a result selects it, double-clicking accepts it and the scroll
+    /// wheel moves the selection
+    #[serde(default)]
+    mouse: bool,
+    /// the initial sort order
+    #[serde(default)]
+    sort: SortOrder,
+    /// combines the confidence of each entry with how well its title matches
+    /// the query, which is given this weight (between 0 and 1). the results
+    /// are ordered by the combined confidence
+    fuzzy_rerank: Option<f64>,
+    /// collapses entries with the same identifier into the one with the
+    /// highest confidence
+    #[serde(default)]
+    dedupe: bool,
+    #[serde(default)]
+    ansi: AnsiMode,
+    #[serde(default)]
+    cursor_positioning: CursorPositioning,
+    /// draws the picker on the alternate screen, restoring the terminal
+    /// content once it exits
+    #[serde(default)]
+    alternate_screen: bool,
+    /// the number of rows taken by the picker, which makes room for them below
+    /// the cursor at startup. the whole terminal height is used if unset
+    height: Option<Height>,
+    #[serde(default)]
+    color_depth: ColorDepth,
+    /// the colors of the interface
+    #[serde(default)]
+    theme: Theme,
+    #[serde(
+        default = "default_output_template",
+        deserialize_with = "template::deserialize_expanded"
+    )]
+    output_template: String,
+    /// accepting an entry of kind `directory` re-queries the backend with the
+    /// entry identifier as `{path}` instead of exiting
+    #[serde(default)]
+    drill_down: bool,
+    /// what to do with the accepted entry instead of printing it
+    accept_action: Option<Action>,
+    /// an entry for creating the query, shown when there are few results
+    create_entry: Option<CreateEntry>,
+    /// allows marking several entries with tab, which are all accepted
+    #[serde(default)]
+    multi_select: bool,
+    /// shown in front of the selected row
+    #[serde(default)]
+    pointer: String,
+    /// shown in front of marked entries, see `multi_select`
+    #[serde(default = "default_marker")]
+    marker: String,
+    /// restores the query of the last run of this profile (see `name`)
+    #[serde(default)]
+    remember_query: bool,
+    /// commands bound to keys, run in the background for the selected entry
+    #[serde(default)]
+    execute_silent: Vec<SilentAction>,
+    /// picker actions bound to keys, e.g. `"ctrl+j": "down"`, which take
+    /// precedence over the default bindings
+    #[serde(default)]
+    keybindings: BTreeMap<String, KeyAction>,
+    /// records accepted queries, which can be searched with ctrl+r
+    #[serde(default)]
+    query_history: bool,
+    /// allows pinning entries with alt+i, which are shown first
+    #[serde(default)]
+    pinning: bool,
+    /// records the accepted entries, which the `frecency` sort order puts
+    /// first
+    #[serde(default)]
+    frecency: bool,
+    /// appends a record of every accepted entry to a log, for analyzing the
+    /// ranking of the results
+    #[serde(default)]
+    selection_log: bool,
+    /// filters the results of the last search against the query while the
+    /// search for it runs
+    #[serde(default)]
+    local_filter: bool,
+    /// dims the results of the previous query while the search for the
+    /// current one runs
+    #[serde(default)]
+    dim_stale_results: bool,
+    /// highlights the parts of each result that match the query
+    #[serde(default)]
+    highlight_matches: bool,
+    /// shows how long the last search took above the results
+    #[serde(default)]
+    show_latency: bool,
+    /// shows a spinner at the end of the query line while a search is running
+    #[serde(default)]
+    spinner: bool,
+    /// moves the status line below the results, starting with the number of
+    /// results, the search latency, the source of the selected entry and its
+    /// position
+    #[serde(default)]
+    status_bar: bool,
+    /// searches taking longer than this are shown as slow, even if
+    /// `show_latency` is disabled
+    #[serde(default = "default_slow_search_millis")]
+    slow_search_millis: u64,
+    /// re-runs the current search periodically (watch mode)
+    refresh_millis: Option<u64>,
+    /// how long a search may take before it's abandoned (and its command
+    /// killed), searches never time out if unset
+    search_timeout_millis: Option<u64>,
+    /// retries failed searches before showing the error
+    retry: Option<Retry>,
+    /// queries shorter than this (in characters) aren't searched for
+    #[serde(default)]
+    min_query_length: usize,
+    /// reuses the results of recent queries instead of searching again
+    cache: Option<CacheConfig>,
+    #[serde(default)]
+    limits: Limits,
 }
 
-#[derive(Deserialize)]
-struct QueryCommand {
-    executable: String,
-    args: Vec<String>,
+#[derive(Deserialize, JsonSchema)]
+struct Retry {
+    /// how many times a failed search is retried
+    #[serde(default = "default_retry_attempts")]
+    attempts: u32,
+    /// how long to wait before the first retry, doubled for each of the others
+    #[serde(default = "default_retry_backoff_millis")]
+    backoff_millis: u64,
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let config = serde_json::from_reader::<_, Config>(stdin())
-        .context("unable to load config from stdin")?;
-    if let Some(identifier) = run(config).await? {
-        eprintln!("{}", identifier);
+fn default_retry_attempts() -> u32 {
+    2
+}
+
+fn default_retry_backoff_millis() -> u64 {
+    200
+}
+
+/// caps on what a backend may send, beyond which the results are truncated
+#[derive(Deserialize, JsonSchema, Clone, Copy)]
+#[serde(default)]
+struct Limits {
+    max_output_bytes: u64,
+    max_results: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_output_bytes: 256 * 1024 * 1024,
+            max_results: 1_000_000,
+        }
     }
+}
 
-    Ok(())
+/// adds what schemars doesn't know about to the config schema: the
+/// `query_command` alias of `source`, and configs with several profiles
+fn extend_config_schema(schema: &mut schemars::Schema) {
+    let Some(object) = schema.as_object_mut() else {
+        return;
+    };
+    if let Some(Value::Object(properties)) = object.get_mut("properties") {
+        if let Some(source) = properties.get("source").cloned() {
+            properties.insert("query_command".into(), source);
+        }
+        properties.insert(
+            "profiles".into(),
+            serde_json::json!({
+                "description": "named configs, one of which is selected with --profile",
+                "type": "object",
+                "additionalProperties": { "$ref": "#" },
+            }),
+        );
+    }
+    object.remove("required");
+    object.insert(
+        "oneOf".into(),
+        serde_json::json!([
+            { "required": ["source"] },
+            { "required": ["query_command"] },
+            { "required": ["profiles"] },
+        ]),
+    );
 }
 
-async fn run(config: Config) -> anyhow::Result<Option<String>> {
-    let mut out = stdout();
-    enable_raw_mode()?;
-    let mut event_stream = EventStream::new();
-
-    execute!(out, Print(QUERY_PREFIX), SavePosition)?;
-    let mut query = String::new();
-    let mut selected_index = 0;
-    let mut current_result: Option<SearchResult> = None;
-
-    let search_future = Fuse::terminated();
-    pin_mut!(search_future);
-    let result = loop {
-        let mut next_event = event_stream.next().fuse();
-
-        futures::select! {
-            maybe_event = next_event => {
-                match maybe_event {
-                    Some(Ok(event)) => {
-                        match event {
-                            Event::Key(key) => {
-                                match key.code {
-                                    KeyCode::Char(c) => {
-                                        query.push(c);
-                                        update_query(&mut out, &query)?;
-                                        search_future.set(Box::new(search(&config, query.clone())).fuse());
-                                    }
-
-                                    KeyCode::Backspace => {
-                                        query.pop();
-                                        update_query(&mut out, &query)?;
-                                        search_future.set(Box::new(search(&config, query.clone())).fuse());
-                                    }
-
-                                    KeyCode::Up => {
-                                        if let Some(result) = current_result.as_ref() {
-                                            let num_results = result.results.len();
-                                            if num_results > 0 {
-                                                selected_index = (selected_index + num_results - 1) % num_results;
-                                                update_results(&mut out, &config, &current_result, selected_index)?;
-                                            }
-                                        }
-                                    }
-
-                                    KeyCode::Down => {
-                                        if let Some(result) = current_result.as_ref() {
-                                            let num_results = result.results.len();
-                                            if num_results > 0 {
-                                                selected_index = (selected_index + 1) % num_results;
-                                                update_results(&mut out, &config, &current_result, selected_index)?;
-                                            }
-                                        }
-                                    }
-
-                                    KeyCode::Esc => {
-                                        break None;
-                                    }
-
-                                    KeyCode::Enter => {
-                                        if let Some(result) = current_result.as_ref() {
-                                            if let Some(entry) = result.results.get(selected_index) {
-                                                break Some(entry.identifier.clone());
-                                            }
-                                        }
-                                    }
-
-                                    _ => {}
-                                }
-                            }
-
-                            Event::Resize(_, _) => {
-                                update_results(&mut out, &config, &current_result, selected_index)?;
-                            }
-
-                            _ => {}
-                        }
-                    }
+fn default_timeout_millis() -> u64 {
+    100
+}
 
-                    Some(Err(error)) => {
-                        return Err(error.into());
-                    }
+fn default_marker() -> String {
+    "* ".into()
+}
+
+fn default_display_template() -> String {
+    "{title}".into()
+}
+
+fn default_slow_search_millis() -> u64 {
+    1000
+}
+
+fn default_prompt() -> String {
+    "Search > ".into()
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct CreateEntry {
+    /// a template with the `{query}` variable
+    #[serde(default = "default_create_title")]
+    title: String,
+    /// the entry is shown when there are at most this many results
+    #[serde(default)]
+    threshold: usize,
+    /// replaces `accept_action` when the create entry is accepted
+    action: Option<Action>,
+    /// replaces `output_template` when the create entry is accepted
+    output_template: Option<String>,
+}
+
+fn default_create_title() -> String {
+    "Create '{query}'".into()
+}
+
+fn default_output_template() -> String {
+    "{identifier}".into()
+}
+
+#[derive(Deserialize, JsonSchema, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum NavigationMode {
+    /// the highlight moves over a stable ordering of the results, scrolling
+    /// the list when the selection leaves the screen
+    #[default]
+    Fixed,
+    /// the results are rotated so that the selected entry is always on top
+    Rotate,
+}
+
+/// the order in which the results are displayed, cycled with ctrl+s
+#[derive(Deserialize, JsonSchema, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum SortOrder {
+    /// the order the backend sent the results in
+    #[default]
+    Backend,
+    /// highest confidence first
+    Confidence,
+    /// alphabetically by title, ignoring case
+    Title,
+    /// like `title`, but with the numbers in titles compared by value, e.g.
+    /// `file2` before `file10`
+    Natural,
+    /// the most frecently accepted entries first, then the others in backend
+    /// order, available if `frecency` is enabled
+    Frecency,
+}
+
+impl SortOrder {
+    fn next(self) -> Self {
+        match self {
+            SortOrder::Backend => SortOrder::Confidence,
+            SortOrder::Confidence => SortOrder::Title,
+            SortOrder::Title => SortOrder::Natural,
+            SortOrder::Natural => SortOrder::Frecency,
+            SortOrder::Frecency => SortOrder::Backend,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SortOrder::Backend => "backend",
+            SortOrder::Confidence => "confidence",
+            SortOrder::Title => "title",
+            SortOrder::Natural => "natural",
+            SortOrder::Frecency => "frecency",
+        }
+    }
+}
+
+/// how the cursor is moved back to the query after drawing the results
+#[derive(Deserialize, JsonSchema, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum CursorPositioning {
+    /// absolute positioning under terminal multiplexers, save/restore
+    /// otherwise
+    #[default]
+    Auto,
+    /// the DEC save/restore cursor sequences
+    SaveRestore,
+    /// absolute positioning, relative to the row the picker started on
+    Absolute,
+}
+
+/// the number of rows taken by the picker, including the query line
+#[derive(Clone, Copy)]
+enum Height {
+    Rows(u16),
+    /// a percentage of the terminal height
+    Percent(u16),
+}
+
+impl Height {
+    /// the number of rows in a terminal with `term_height` rows, leaving room
+    /// for at least one row below the query
+    fn rows(self, term_height: u16) -> u16 {
+        let rows = match self {
+            Height::Rows(rows) => rows,
+            Height::Percent(percent) => {
+                (u32::from(term_height) * u32::from(percent.min(100)) / 100) as u16
+            }
+        };
+        rows.clamp(2, term_height.max(2))
+    }
+}
+
+impl FromStr for Height {
+    type Err = String;
+
+    fn from_str(height: &str) -> Result<Self, String> {
+        let invalid = |_| format!("invalid height: {height}, expected e.g. 10 or 40%");
+        match height.strip_suffix('%') {
+            Some(percent) => Ok(Height::Percent(percent.parse().map_err(invalid)?)),
+            None => Ok(Height::Rows(height.parse().map_err(invalid)?)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Height {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum HeightConfig {
+            Rows(u16),
+            Text(String),
+        }
+
+        match HeightConfig::deserialize(deserializer)? {
+            HeightConfig::Rows(rows) => Ok(Height::Rows(rows)),
+            HeightConfig::Text(height) => height.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+impl JsonSchema for Height {
+    fn schema_name() -> Cow<'static, str> {
+        "Height".into()
+    }
 
-                    None => {}
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "anyOf": [
+                {
+                    "type": "integer",
+                    "minimum": 0,
+                    "description": "a number of rows"
+                },
+                {
+                    "type": "string",
+                    "pattern": "^[0-9]+%?$",
+                    "description": "a number of rows, or a percentage of the terminal height like \"40%\""
                 }
+            ]
+        })
+    }
+}
+
+/// the number of colors the terminal supports, colors are mapped down to it
+#[derive(Deserialize, JsonSchema, Default, Clone, Copy, PartialEq, Eq)]
+enum ColorDepth {
+    /// detected from `COLORTERM`, `TERM` and terminfo
+    #[default]
+    #[serde(rename = "auto")]
+    Auto,
+    #[serde(rename = "truecolor")]
+    TrueColor,
+    #[serde(rename = "256")]
+    Ansi256,
+    #[serde(rename = "16")]
+    Ansi16,
+}
+
+/// how ANSI escape sequences in backend-provided text are handled
+#[derive(Deserialize, JsonSchema, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum AnsiMode {
+    /// escape sequences are displayed in a printable form, e.g. `^[[31m`
+    #[default]
+    Escape,
+    /// escape sequences are removed
+    Strip,
+    /// colors and other text attributes are displayed, other escape sequences
+    /// are removed
+    Render,
+}
+
+impl AnsiMode {
+    fn clean(self, text: &str) -> Cow<'_, str> {
+        match self {
+            AnsiMode::Escape => sanitize(text),
+            AnsiMode::Strip => match strip_ansi(text) {
+                Cow::Borrowed(text) => sanitize(text),
+                Cow::Owned(text) => Cow::Owned(sanitize(&text).into_owned()),
+            },
+            AnsiMode::Render => sanitize_keeping_sgr(text),
+        }
+    }
+
+    /// cleans every string inside of a json value
+    fn clean_value(self, value: &Value) -> Value {
+        match value {
+            Value::String(text) => Value::String(self.clean(text).into_owned()),
+            Value::Array(values) => {
+                Value::Array(values.iter().map(|v| self.clean_value(v)).collect())
             }
+            Value::Object(map) => Value::Object(self.clean_map(map)),
+            value => value.clone(),
+        }
+    }
 
-            search_result = search_future => {
-                match search_result {
-                    Ok(result) => {
-                        current_result.replace(result);
-                        update_results(&mut out, &config, &current_result, selected_index)?;
-                        selected_index = 0;
-                    }
+    fn clean_map(self, map: &Map<String, Value>) -> Map<String, Value> {
+        map.iter()
+            .map(|(key, value)| (self.clean(key).into_owned(), self.clean_value(value)))
+            .collect()
+    }
+}
 
-                    Err(err) => {
-                        execute!(out, Print(format_args!("\r\n{}", err)))?;
-                        current_result.take();
-                        update_results(&mut out, &config, &current_result, selected_index)?;
-                        selected_index = 0;
-                    }
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    if cli.schema {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&schemars::schema_for!(Config))?
+        );
+        return Ok(());
+    }
+    if let Some(path) = &cli.init_config {
+        return init_config(path.as_deref());
+    }
+    let mut input = stdin().lock();
+    let format = cli.config_format;
+    let profile = cli.profile.as_deref();
+    // piped configs take precedence over the default one
+    let path = cli
+        .config
+        .clone()
+        .or_else(|| stdin().is_terminal().then(default_config_path).flatten());
+    let (mut config, format) = match &path {
+        Some(path) => {
+            let file = File::open(path)
+                .with_context(|| format!("unable to open config {}", path.display()))?;
+            let format = format.or_else(|| ConfigFormat::from_extension(path));
+            load_config(&mut BufReader::new(file), format, profile, false)
+                .with_context(|| format!("unable to load config from {}", path.display()))?
+        }
+        None if stdin().is_terminal() => anyhow::bail!(
+            "no config found, pipe one into stdin, pass --config or create \
+             $XDG_CONFIG_HOME/search-tui/config.json"
+        ),
+        None => load_config(&mut input, format, profile, true)
+            .context("unable to load config from stdin")?,
+    };
+    cli.apply(&mut config);
+    if cli.check_config {
+        let problems = check::check(&config);
+        for problem in &problems {
+            println!("{}: {}", problem.location, problem.message);
+        }
+        match problems.len() {
+            0 => return Ok(()),
+            1 => anyhow::bail!("the config has 1 problem"),
+            num_problems => anyhow::bail!("the config has {num_problems} problems"),
+        }
+    }
+    if let Some(location) = check::exec_with_multi_select(&config) {
+        anyhow::bail!("{location} can't exec with multi_select, which accepts several entries");
+    }
+    if let Source::BuiltIn(source::BuiltInSource::Stdin(source)) = &mut config.source {
+        if path.is_none() && format != ConfigFormat::Json {
+            anyhow::bail!("the stdin source requires a json config, followed by the items");
+        }
+        if stdin().is_terminal() {
+            anyhow::bail!("the stdin source requires items piped into stdin");
+        }
+        source.delimiter = cli.delimiter.clone().or(source.delimiter.take());
+        source.nth = cli.nth.clone().or(source.nth.take());
+        source.with_nth = cli.with_nth.clone().or(source.with_nth.take());
+        source.read(&mut input, cli.read0, path.is_none())?
+    }
+    #[cfg(feature = "clipboard")]
+    if cli.clipboard_daemon {
+        return match &config.source {
+            Source::BuiltIn(source::BuiltInSource::Clipboard(clipboard)) => clipboard.run_daemon(),
+            _ => Err(anyhow::anyhow!(
+                "--clipboard-daemon requires a clipboard source"
+            )),
+        };
+    }
+
+    let accepted = run(&config, &mut TerminalFrontend::new(&config)?).await?;
+    for entry in accepted {
+        let create = config.create_entry.as_ref().filter(|_| entry.is_created());
+        if create.is_none() {
+            config.source.accept(&entry)?;
+        }
+        let accept_action = match create {
+            Some(create) => create.action.as_ref(),
+            None => config.accept_action.as_ref(),
+        };
+        let output_template = create
+            .and_then(|create| create.output_template.as_ref())
+            .unwrap_or(&config.output_template);
+        match accept_action {
+            Some(action) => action.run(&entry)?,
+            None => {
+                let output = Template::new(output_template)?.render(&EntryContext::new(&entry))?;
+                if cli.print0 {
+                    eprint!("{output}\0");
+                } else {
+                    eprintln!("{output}");
                 }
             }
         }
+    }
+
+    Ok(())
+}
+
+/// `$XDG_CONFIG_HOME/search-tui`, with `XDG_CONFIG_HOME` defaulting to
+/// `~/.config`
+fn config_dir() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(dir.join("search-tui"))
+}
+
+/// `$XDG_CONFIG_HOME/search-tui/config.{json,toml,yaml,yml}`, if one of them
+/// exists
+fn default_config_path() -> Option<PathBuf> {
+    let dir = config_dir()?;
+    ["json", "toml", "yaml", "yml"]
+        .into_iter()
+        .map(|extension| dir.join("config").with_extension(extension))
+        .find(|path| path.is_file())
+}
+
+/// writes the starter config to `path`, stdout if it's `-`, or the default
+/// config path if unset. existing configs are never overwritten
+fn init_config(path: Option<&Path>) -> anyhow::Result<()> {
+    const STARTER_CONFIG: &str = include_str!("starter_config.toml");
+    if path == Some(Path::new("-")) {
+        print!("{STARTER_CONFIG}");
+        return Ok(());
+    }
+    let path = if let Some(path) = path {
+        path.to_path_buf()
+    } else {
+        if let Some(existing) = default_config_path() {
+            anyhow::bail!("a config already exists at {}", existing.display());
+        }
+        let dir = config_dir().context("unable to find the config directory")?;
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("unable to create {}", dir.display()))?;
+        dir.join("config.toml")
+    };
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .with_context(|| format!("unable to create {}", path.display()))?;
+    io::Write::write_all(&mut file, STARTER_CONFIG.as_bytes())
+        .with_context(|| format!("unable to write {}", path.display()))?;
+    println!("wrote a starter config to {}", path.display());
+    Ok(())
+}
+
+/// reads a config in `format`, which is guessed if unset. if `items_follow`,
+/// a json config may be followed by the items of a stdin source
+fn load_config(
+    input: &mut impl BufRead,
+    format: Option<ConfigFormat>,
+    profile: Option<&str>,
+    items_follow: bool,
+) -> anyhow::Result<(Config, ConfigFormat)> {
+    let format = match format {
+        Some(format) => format,
+        None => ConfigFormat::sniff(input)?,
+    };
+    let config = match format {
+        ConfigFormat::Json => {
+            let mut deserializer = serde_json::Deserializer::from_reader(input);
+            let config = deserialize_config(&mut deserializer, profile)?;
+            let is_stdin = matches!(
+                config.source,
+                Source::BuiltIn(source::BuiltInSource::Stdin(_))
+            );
+            if !(items_follow && is_stdin) {
+                deserializer.end()?;
+            }
+            config
+        }
+        ConfigFormat::Toml => deserialize_config(
+            toml::Deserializer::new(&io::read_to_string(input)?),
+            profile,
+        )?,
+        ConfigFormat::Yaml => deserialize_config(
+            serde_yaml::Deserializer::from_str(&io::read_to_string(input)?),
+            profile,
+        )?,
     };
-    
-    execute!(out, Print("\r"), Clear(ClearType::FromCursorDown))?;                
-    disable_raw_mode()?;
-    Ok(result)
+    Ok((config, format))
 }
 
+/// a config with several named profiles, selected with `--profile`
 #[derive(Deserialize)]
-struct SearchResult {
-    results: Vec<SearchResultEntry>,
+struct Profiles {
+    profiles: BTreeMap<String, Config>,
 }
 
-#[derive(Deserialize)]
-struct SearchResultEntry {
-    confidence: f64,
-    identifier: String,
-    title: String,
+/// deserializes a config, or the profile named `profile` of a config with
+/// several of them
+fn deserialize_config<'de, D>(deserializer: D, profile: Option<&str>) -> anyhow::Result<Config>
+where
+    D: Deserializer<'de>,
+    D::Error: Send + Sync + 'static,
+{
+    let Some(profile) = profile else {
+        return Ok(Config::deserialize(deserializer)?);
+    };
+    let mut profiles = Profiles::deserialize(deserializer)?.profiles;
+    let mut config = profiles.remove(profile).with_context(|| {
+        let names: Vec<_> = profiles.keys().map(String::as_str).collect();
+        format!(
+            "no profile named {profile:?}, the profiles are {}",
+            names.join(", ")
+        )
+    })?;
+    // the profile name also keys the state kept between runs
+    if config.name.is_empty() {
+        config.name = profile.to_string();
+    }
+    Ok(config)
 }
 
-fn update_query(out: &mut Stdout, query: &str) -> anyhow::Result<()> {
-    execute!(
-        out,
-        RestorePosition,
-        Print("\r"),
-        MoveRight(QUERY_PREFIX.len() as u16),
-        Clear(ClearType::UntilNewLine),
-        Print(query),
-        SavePosition
-    )?;
-    Ok(())
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ConfigFormat {
+    Json,
+    Toml,
+    #[value(alias = "yml")]
+    Yaml,
 }
 
-async fn search(config: &Config, query: String) -> anyhow::Result<SearchResult> {
-    #[derive(Serialize)]
-    struct Context {
-        query: String,
-        query_escaped: String,
+impl ConfigFormat {
+    fn from_extension(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "json" => Some(ConfigFormat::Json),
+            "toml" => Some(ConfigFormat::Toml),
+            "yaml" | "yml" => Some(ConfigFormat::Yaml),
+            _ => None,
+        }
     }
 
-    tokio::time::sleep(Duration::from_millis(config.timeout_millis)).await;
+    /// tells toml from yaml by the first line that isn't a comment, which is
+    /// a table header or a `key = value` pair in toml
+    fn guess(config: &[u8]) -> Self {
+        let line = config
+            .split(|byte| *byte == b'\n')
+            .map(<[u8]>::trim_ascii)
+            .find(|line| !line.is_empty() && !line.starts_with(b"#"));
+        match line {
+            Some([b'[', ..]) => ConfigFormat::Toml,
+            Some(line) => {
+                let equals = line.iter().position(|byte| *byte == b'=');
+                let colon = line.iter().position(|byte| *byte == b':');
+                match (equals, colon) {
+                    (Some(equals), Some(colon)) if equals < colon => ConfigFormat::Toml,
+                    (Some(_), None) => ConfigFormat::Toml,
+                    _ => ConfigFormat::Yaml,
+                }
+            }
+            None => ConfigFormat::Yaml,
+        }
+    }
 
-    let context = Context {
-        query_escaped: query.escape_debug().to_string(),
-        query,
-    };
+    /// guesses the format from the start of the config, which is `{` for
+    /// json. leading whitespace is skipped
+    fn sniff(input: &mut impl BufRead) -> anyhow::Result<Self> {
+        loop {
+            let buf = input.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(ConfigFormat::Json);
+            }
+            match buf.iter().position(|byte| !byte.is_ascii_whitespace()) {
+                Some(index) => {
+                    let format = match buf[index] {
+                        b'{' => ConfigFormat::Json,
+                        _ => ConfigFormat::guess(&buf[index..]),
+                    };
+                    input.consume(index);
+                    return Ok(format);
+                }
+                None => {
+                    let len = buf.len();
+                    input.consume(len);
+                }
+            }
+        }
+    }
+}
 
-    let template = |template_string| Template::new(template_string)?.render(&context);
+/// the template context of an accepted entry, used by the output template and
+/// actions
+#[derive(Serialize)]
+struct EntryContext<'a> {
+    identifier: &'a str,
+    title: &'a str,
+    confidence: f64,
+    extra: &'a Map<String, Value>,
+}
 
-    let process_output = Command::new(template(&config.query_command.executable)?)
-        .args(
-            config
-                .query_command
-                .args
-                .iter()
-                .map(|arg| template(arg))
-                .collect::<anyhow::Result<Vec<_>>>()?,
-        )
-        .output()?;
-
-    process_output
-        .status
-        .exit_ok()
-        .map_err(|err| {
-            let error = std::str::from_utf8(&process_output.stderr)
-                .unwrap_or("unable to decode stderr as utf-8");
-            anyhow::anyhow!("{error}, status error {err}")
-        })
-        .and_then(|_| {
-            let result = serde_json::from_slice::<SearchResult>(&process_output.stdout)?;
-            Ok(result)
-        })
+impl<'a> EntryContext<'a> {
+    fn new(entry: &'a SearchResultEntry) -> Self {
+        Self {
+            identifier: &entry.identifier,
+            title: &entry.title,
+            confidence: entry.confidence,
+            extra: &entry.extra,
+        }
+    }
 }
 
-fn update_results(
-    out: &mut Stdout,
+/// runs the picker, returning the accepted entries
+async fn run(
     config: &Config,
-    result: &Option<SearchResult>,
-    selected_index: usize,
-) -> anyhow::Result<()> {
-    #[derive(Serialize)]
-    struct Context<'a> {
-        identifier: &'a str,
-        title: &'a str,
-        confidence: f64,
-        index: usize,
-        one_based_index: usize,
-        display_index: usize,
-        one_based_display_index: usize,
-    }
-    
-    execute!(out, Clear(ClearType::FromCursorDown))?;
-    let _guard = RestorePositionRAII;
-    if let Some(result) = result {
-        let num_results = result.results.len();
-        if num_results == 0 {
-            queue!(out, Print("\r\nno entries found"))?;
+    frontend: &mut impl Frontend,
+) -> anyhow::Result<Vec<SearchResultEntry>> {
+    let mut state = State::new(config);
+    if config.query_history {
+        state.set_query_history(history::load_queries(&config.name)?);
+    }
+    if config.pinning {
+        state.set_pinned(history::load_pinned(&config.name)?);
+    }
+    if config.frecency {
+        state.set_frecencies(history::load_entry_frecencies(&config.name)?);
+    }
+    let mut refresh_interval = config.refresh_millis.map(|millis| {
+        let mut interval = tokio::time::interval(Duration::from_millis(millis));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        interval
+    });
+
+    let mut pending_input = if config.remember_query {
+        history::load_last_query(&config.name)?.map(Input::RestoreQuery)
+    } else {
+        None
+    };
+
+    let mut search_updates = stream::pending().boxed_local();
+    // when the query first changed since the last search started, and when
+    // the next search starts
+    let mut debounce: Option<(Instant, Instant)> = None;
+    let mut cache = config.cache.as_ref().map(ResultCache::new);
+    // the query and path of the search in flight, whose results are cached
+    let mut searching_for = None;
+    let preview_future = Fuse::terminated();
+    pin_mut!(preview_future);
+    let mut silent_actions = FuturesUnordered::new();
+    loop {
+        let event = if let Some(input) = pending_input.take() {
+            FrontendEvent::Input(input)
         } else {
-            let term_height = size()?.1;
-            let max_results_shown: usize = (term_height.max(2) - 2).into();
-            let display_template = Template::new(&config.display_template)?;
-            for index in 0..num_results.min(max_results_shown) {
-                queue!(out, Print("\r\n"))?;
-                if index == 0 {
-                    queue!(
-                        out,
-                        SetForegroundColor(Color::Black),
-                        SetBackgroundColor(Color::White)
-                    )?;
+            let next_event = frontend.poll().fuse();
+            let next_refresh = tick(refresh_interval.as_mut()).fuse();
+            let next_search_update = search_updates.next().fuse();
+            pin_mut!(next_event, next_refresh, next_search_update);
+
+            futures::select! {
+                event = next_event => event?,
+                () = next_refresh => FrontendEvent::Input(Input::Refresh),
+                input = next_search_update => FrontendEvent::Input(input.expect("search updates never end")),
+                input = preview_future => FrontendEvent::Input(input),
+                message = silent_actions.select_next_some() => FrontendEvent::Input(Input::ExecuteSilentFinished(message)),
+            }
+        };
+        if let (FrontendEvent::Input(Input::SearchFinished(Ok(result))), Some(cache)) =
+            (&event, &mut cache)
+        {
+            if let Some((query, path)) = searching_for.take() {
+                cache.insert(query, path, result.clone());
+            }
+        }
+        let input = match event {
+            FrontendEvent::Input(input) => input,
+            FrontendEvent::Redraw => {
+                frontend.render(&state, Change::All)?;
+                continue;
+            }
+        };
+
+        for effect in state.update(input) {
+            match effect {
+                Effect::Search {
+                    query,
+                    path,
+                    refresh,
+                } => {
+                    let cached = cache
+                        .as_mut()
+                        .filter(|_| !refresh)
+                        .and_then(|cache| cache.get(&query, &path));
+                    if let Some(result) = cached {
+                        search_updates = stream::pending().boxed_local();
+                        searching_for = None;
+                        pending_input = Some(Input::SearchFinished(Ok(result)));
+                        continue;
+                    }
+                    searching_for = Some((query.clone(), path.clone()));
+                    let now = Instant::now();
+                    let first_change = match debounce {
+                        Some((first_change, starts_at)) if now < starts_at => first_change,
+                        _ => now,
+                    };
+                    let mut delay = Duration::from_millis(config.timeout_millis);
+                    if let Some(max_wait_millis) = config.max_wait_millis {
+                        let deadline = first_change + Duration::from_millis(max_wait_millis);
+                        delay = delay.min(deadline.saturating_duration_since(now));
+                    }
+                    debounce = Some((first_change, now + delay));
+                    search_updates = search(config, query, path, delay).boxed_local();
+                    // the stale results have been dimmed, filtered or
+                    // highlighted for the new query
+                    if config.dim_stale_results || config.local_filter || config.highlight_matches {
+                        frontend.render(&state, Change::Results)?;
+                    }
+                }
+                Effect::SearchSkipped => {
+                    search_updates = stream::pending().boxed_local();
+                    searching_for = None;
+                    debounce = None;
+                    frontend.render(&state, Change::Results)?;
+                }
+                Effect::Preview(entry) => {
+                    if let Some(preview) = &config.preview {
+                        preview_future.set(Box::new(run_preview(preview, entry)).fuse());
+                    }
+                }
+                Effect::ExecuteSilent { index, entry } => {
+                    if let Some(action) = config.execute_silent.get(index) {
+                        silent_actions.push(async move { action.run(&entry).await });
+                    }
                 }
-                let entry_index = (selected_index + index) % num_results;
-                let entry = result.results.get(entry_index).unwrap();
-                let display_string = display_template.render(&Context {
-                    identifier: &entry.identifier,
-                    title: &entry.title,
-                    confidence: entry.confidence,
-                    index: entry_index,
-                    one_based_index: entry_index + 1,
-                    display_index: index,
-                    one_based_display_index: index + 1,
-                })?;
-                queue!(out, Clear(ClearType::UntilNewLine), Print(display_string))?;
-                if index == 0 {
-                    queue!(out, ResetColor)?;
+                Effect::QueryChanged => frontend.render(&state, Change::Query)?,
+                Effect::ResultsChanged => frontend.render(&state, Change::Results)?,
+                Effect::SelectionChanged => frontend.render(&state, Change::Selection)?,
+                Effect::PreviewChanged => frontend.render(&state, Change::Preview)?,
+                Effect::StatusChanged => frontend.render(&state, Change::Status)?,
+                Effect::PinnedChanged(pinned) => {
+                    history::save_pinned(&config.name, &pinned)?;
+                    frontend.render(&state, Change::Results)?;
+                }
+                Effect::Error(message) => frontend.show_error(&state, &message)?,
+                Effect::Copy(text) => {
+                    if let Err(err) = action::copy_to_clipboard(&text) {
+                        frontend.show_error(&state, &err.to_string())?;
+                    }
+                }
+                Effect::Exit(entries) => {
+                    if config.remember_query {
+                        history::save_last_query(&config.name, state.query())?;
+                    }
+                    if config.query_history && !entries.is_empty() && !state.query().is_empty() {
+                        history::add_query(&config.name, state.query())?;
+                    }
+                    if config.frecency && !entries.is_empty() {
+                        history::add_entries(
+                            &config.name,
+                            entries.iter().map(|entry| entry.identifier.as_str()),
+                        )?;
+                    }
+                    if config.selection_log {
+                        for entry in &entries {
+                            history::log_selection(&history::Selection {
+                                profile: &config.name,
+                                query: state.query(),
+                                identifier: &entry.identifier,
+                                rank: state.rank(entry),
+                            })?;
+                        }
+                    }
+                    return Ok(entries);
                 }
             }
         }
     }
-
-    Ok(())
 }
 
-struct Template<'a> {
-    template: TinyTemplate<'a>,
+async fn run_preview(preview: &Preview, entry: SearchResultEntry) -> Input {
+    Input::PreviewFinished {
+        content: preview.run(&entry).await,
+        identifier: entry.identifier,
+    }
 }
 
-impl<'a> Template<'a> {
-    pub fn new(template_string: &'a str) -> anyhow::Result<Self> {
-        let mut template = TinyTemplate::new();
-        template.add_template("main", template_string)?;
-        Ok(Self { template })
+/// completes on the next tick of `interval`, or never if there is none
+async fn tick(interval: Option<&mut Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => futures::future::pending().await,
     }
+}
+
+#[derive(Deserialize, Clone)]
+struct SearchResult {
+    results: Vec<SearchResultEntry>,
+    /// a rewrite of the query, e.g. a spelling correction
+    #[serde(default)]
+    suggested_query: Option<String>,
+    /// non-fatal problems with the search, e.g. truncated results
+    #[serde(skip)]
+    warnings: Vec<String>,
+    /// how long the backend took, if it was run
+    #[serde(skip)]
+    duration: Option<Duration>,
+}
 
-    pub fn render<C: serde::Serialize>(&self, context: &C) -> anyhow::Result<String> {
-        Ok(self.template.render("main", context)?)
+impl SearchResult {
+    fn new(results: Vec<SearchResultEntry>) -> Self {
+        Self {
+            results,
+            suggested_query: None,
+            warnings: Vec::new(),
+            duration: None,
+        }
     }
 }
 
-struct RestorePositionRAII;
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+struct SearchResultEntry {
+    confidence: f64,
+    identifier: String,
+    title: String,
+    /// any other fields of the entry, available to templates as `{extra.*}`
+    #[serde(flatten)]
+    extra: Map<String, Value>,
+}
+
+impl SearchResultEntry {
+    /// the entry accepted when the create entry is, whose identifier is the
+    /// query
+    fn created(query: &str) -> Self {
+        let mut extra = Map::new();
+        extra.insert("kind".into(), "create".into());
+        Self {
+            confidence: 0.0,
+            identifier: query.to_string(),
+            title: query.to_string(),
+            extra,
+        }
+    }
+
+    fn is_directory(&self) -> bool {
+        self.extra.get("kind").and_then(Value::as_str) == Some("directory")
+    }
 
-impl Drop for RestorePositionRAII {
-    fn drop(&mut self) {
-        execute!(stdout(), RestorePosition).expect("unable to restore cursor position");
+    /// the name of the source the entry comes from, for backends merging
+    /// several of them
+    fn source(&self) -> Option<&str> {
+        self.extra.get("source").and_then(Value::as_str)
     }
+
+    fn is_created(&self) -> bool {
+        self.extra.get("kind").and_then(Value::as_str) == Some("create")
+    }
+}
+
+/// runs a search once, returning whether it may be retried if it failed, which
+/// is unless it timed out or has shown some of its entries already
+async fn search_attempt(
+    config: &Config,
+    query: &str,
+    path: &str,
+    entries: &mpsc::UnboundedSender<SearchResultEntry>,
+) -> (anyhow::Result<SearchResult>, bool) {
+    let (sender, attempt_entries) = mpsc::unbounded();
+    let mut streamed = false;
+    let forward = attempt_entries.for_each(|entry| {
+        streamed = true;
+        let _ = entries.unbounded_send(entry);
+        future::ready(())
+    });
+    let search = async {
+        let search =
+            config
+                .source
+                .search(query.to_string(), path.to_string(), &config.limits, sender);
+        match config.search_timeout_millis {
+            Some(millis) => tokio::time::timeout(Duration::from_millis(millis), search)
+                .await
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("search timed out after {millis} ms")))
+                .map_err(|err| (err, false)),
+            None => search.await.map_err(|err| (err, true)),
+        }
+    };
+    let (result, ()) = futures::join!(search, forward);
+    match result {
+        Ok(result) => (Ok(result), false),
+        Err((err, retryable)) => (Err(err), retryable && !streamed),
+    }
+}
+
+/// searches after `delay`, which is restarted by every query change (the next
+/// search replacing this one) up to `max_wait_millis`. the entries of streaming
+/// sources are sent in batches as they arrive, before the finished search. the
+/// stream never ends, so that it can be polled until the next search replaces
+/// it
+fn search(
+    config: &Config,
+    query: String,
+    path: String,
+    delay: Duration,
+) -> impl Stream<Item = Input> + '_ {
+    let (sender, entries) = mpsc::unbounded();
+    let finished = async move {
+        tokio::time::sleep(delay).await;
+        let start = Instant::now();
+        let (mut result, mut retryable) = search_attempt(config, &query, &path, &sender).await;
+        if let Some(retry) = &config.retry {
+            let mut backoff = Duration::from_millis(retry.backoff_millis);
+            for _ in 0..retry.attempts {
+                if result.is_ok() || !retryable {
+                    break;
+                }
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                (result, retryable) = search_attempt(config, &query, &path, &sender).await;
+            }
+        }
+        Input::SearchFinished(result.map(|mut result| {
+            result.duration = Some(start.elapsed());
+            result
+        }))
+    };
+    stream::select(
+        entries.ready_chunks(4096).map(Input::SearchProgress),
+        finished.into_stream(),
+    )
+    .chain(stream::pending())
 }
@@ -0,0 +1,79 @@
+//! the preview pane, which shows the output of a command for the selected
+//! entry
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::{source::QueryCommand, EntryContext, SearchResultEntry};
+
+#[derive(Deserialize, JsonSchema)]
+pub struct Preview {
+    /// the preview command, whose `executable` and `args` are templates of the
+    /// selected entry
+    pub command: QueryCommand,
+    /// whether the preview is shown at startup, toggled with alt+p
+    #[serde(default = "default_visible")]
+    pub visible: bool,
+    /// the layouts cycled through with alt+l, the first one being used at
+    /// startup
+    #[serde(default = "default_layouts")]
+    pub layouts: Vec<PreviewLayout>,
+    /// how long the preview of an entry is reused for, previews aren't
+    /// cached if unset
+    pub cache_millis: Option<u64>,
+}
+
+fn default_visible() -> bool {
+    true
+}
+
+fn default_layouts() -> Vec<PreviewLayout> {
+    vec![
+        PreviewLayout {
+            position: PreviewPosition::Right,
+            size: 50,
+        },
+        PreviewLayout {
+            position: PreviewPosition::Bottom,
+            size: 50,
+        },
+    ]
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Copy)]
+pub struct PreviewLayout {
+    pub position: PreviewPosition,
+    /// the percentage of the width (or height) taken by the preview
+    pub size: u16,
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewPosition {
+    Right,
+    Bottom,
+}
+
+impl Preview {
+    /// runs the preview command for `entry`. failures are previewed as well,
+    /// since they are usually what the user wants to see
+    pub async fn run(&self, entry: &SearchResultEntry) -> String {
+        let mut command = match self.command.build(&EntryContext::new(entry)) {
+            Ok(command) => command,
+            Err(err) => return err.to_string(),
+        };
+        let output = tokio::task::spawn_blocking(move || command.output()).await;
+        match output {
+            Ok(Ok(output)) if output.status.success() => {
+                String::from_utf8_lossy(&output.stdout).into_owned()
+            }
+            Ok(Ok(output)) => format!(
+                "{}{}",
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            ),
+            Ok(Err(err)) => format!("unable to run the preview command: {err}"),
+            Err(err) => err.to_string(),
+        }
+    }
+}
@@ -0,0 +1,544 @@
+//! the backends that search results are fetched from
+
+#[cfg(feature = "clipboard")]
+pub mod clipboard;
+mod delimited;
+pub mod file;
+pub mod http;
+mod json;
+pub mod merged;
+pub mod persistent;
+mod process;
+pub mod processes;
+#[cfg(unix)]
+pub mod socket;
+pub mod ssh;
+pub mod stdin;
+pub mod web;
+
+use std::{
+    borrow::Cow,
+    process::{Command, Stdio},
+};
+
+use futures::channel::mpsc::UnboundedSender;
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+
+use crate::{
+    template::{self, Template},
+    Limits, SearchResult, SearchResultEntry,
+};
+use process::SearchProcess;
+
+pub enum Source {
+    /// an external command that prints a [`SearchResult`] as json, configured
+    /// without a `type` property for backwards compatibility
+    Command(QueryCommand),
+    /// a long-lived backend process, configured with `"type": "persistent"`
+    Persistent(persistent::PersistentSource),
+    /// a backend answering searches over http, configured with
+    /// `"type": "http"`
+    Http(http::HttpSource),
+    /// a local daemon answering searches over a unix domain socket,
+    /// configured with `"type": "socket"`
+    #[cfg(unix)]
+    Socket(socket::SocketSource),
+    BuiltIn(BuiltInSource),
+    /// several sources, configured as an array
+    Merged(merged::MergedSource),
+}
+
+pub struct QueryCommand {
+    /// the shell running `script`, if it's set
+    pub executable: String,
+    pub args: Vec<String>,
+    /// a shell script run with `executable -c`, which isn't a template. the
+    /// template variables are passed as `SEARCH_TUI_*` environment variables
+    /// instead
+    pub script: Option<String>,
+    pub format: OutputFormat,
+    /// the delimiter of the columns of delimited output
+    pub delimiter: char,
+    /// the names of the columns of delimited output
+    pub columns: Vec<String>,
+    pub stdin: QueryInput,
+}
+
+/// [`QueryCommand`] as configured, either a shell script or an object
+#[derive(Deserialize, JsonSchema)]
+struct QueryCommandConfig {
+    #[serde(default, deserialize_with = "template::deserialize_expanded_option")]
+    executable: Option<String>,
+    #[serde(default, deserialize_with = "template::deserialize_expanded_list")]
+    args: Vec<String>,
+    /// a shell script run instead of `executable` and `args`, with the
+    /// template variables as `SEARCH_TUI_*` environment variables
+    script: Option<String>,
+    /// the shell running `script`
+    #[serde(
+        default = "default_shell",
+        deserialize_with = "template::deserialize_expanded"
+    )]
+    shell: String,
+    /// the format of the output of query commands
+    #[serde(default)]
+    format: OutputFormat,
+    /// the delimiter of the columns of delimited output, a tab by default
+    #[serde(default = "default_delimiter")]
+    delimiter: char,
+    /// the fields of the entries that the columns of delimited output are,
+    /// e.g. `["identifier", "title", "confidence", "url"]`. empty names skip
+    /// their column
+    #[serde(default = "default_columns")]
+    columns: Vec<String>,
+    /// what query commands read from stdin
+    #[serde(default)]
+    stdin: QueryInput,
+}
+
+fn default_shell() -> String {
+    "sh".to_string()
+}
+
+fn default_delimiter() -> char {
+    '\t'
+}
+
+fn default_columns() -> Vec<String> {
+    vec!["identifier".to_string(), "title".to_string()]
+}
+
+impl<'de> Deserialize<'de> for QueryCommand {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        let config = match value {
+            Value::String(script) => QueryCommandConfig {
+                executable: None,
+                args: Vec::new(),
+                script: Some(script),
+                shell: default_shell(),
+                format: OutputFormat::default(),
+                delimiter: default_delimiter(),
+                columns: default_columns(),
+                stdin: QueryInput::default(),
+            },
+            value => QueryCommandConfig::deserialize(value).map_err(serde::de::Error::custom)?,
+        };
+        let (executable, script) = match (config.executable, config.script) {
+            (Some(executable), None) => (executable, None),
+            (None, Some(script)) => (config.shell, Some(script)),
+            (Some(_), Some(_)) => {
+                return Err(serde::de::Error::custom(
+                    "a command has either an `executable` or a `script`, not both",
+                ))
+            }
+            (None, None) => return Err(serde::de::Error::missing_field("executable")),
+        };
+        Ok(Self {
+            executable,
+            args: config.args,
+            script,
+            format: config.format,
+            delimiter: config.delimiter,
+            columns: config.columns,
+            stdin: config.stdin,
+        })
+    }
+}
+
+/// mirrors the [`Deserialize`] implementation
+impl JsonSchema for QueryCommand {
+    fn schema_name() -> Cow<'static, str> {
+        "QueryCommand".into()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "anyOf": [
+                {
+                    "type": "string",
+                    "description": "a shell script, run with `sh -c`"
+                },
+                {
+                    "allOf": [
+                        generator.subschema_for::<QueryCommandConfig>(),
+                        {
+                            "oneOf": [
+                                { "required": ["executable"] },
+                                { "required": ["script"] }
+                            ]
+                        }
+                    ]
+                }
+            ]
+        })
+    }
+}
+
+#[derive(Deserialize, JsonSchema, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryInput {
+    /// nothing, the query is only passed in the templated arguments
+    #[default]
+    None,
+    /// the query, followed by a newline
+    Query,
+    /// a json object with the `query` and `path`, followed by a newline
+    Json,
+}
+
+#[derive(Deserialize, JsonSchema, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    /// a single [`SearchResult`] object
+    #[default]
+    Json,
+    /// one [`SearchResultEntry`] per line, shown as they are printed
+    Ndjson,
+    /// one entry per line, whose columns are split at `delimiter` and named
+    /// by `columns`, shown as they are printed
+    Delimited,
+    /// a single [`SearchResult`] object encoded as messagepack, which is
+    /// faster to parse than json for tens of thousands of entries
+    Msgpack,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BuiltInSource {
+    #[cfg(feature = "clipboard")]
+    Clipboard(clipboard::ClipboardSource),
+    File(file::FileSource),
+    SshHosts(ssh::SshHostsSource),
+    Processes(processes::ProcessesSource),
+    Stdin(stdin::StdinSource),
+    WebSuggestions(web::WebSuggestionsSource),
+}
+
+impl<'de> Deserialize<'de> for Source {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        #[cfg(unix)]
+        if value.get("type").and_then(Value::as_str) == Some("socket") {
+            return socket::SocketSource::deserialize(value)
+                .map(Source::Socket)
+                .map_err(serde::de::Error::custom);
+        }
+        let source = if let Value::Array(values) = value {
+            merged::MergedSource::from_values(values).map(Source::Merged)
+        } else if value.get("type").and_then(Value::as_str) == Some("persistent") {
+            persistent::PersistentSource::deserialize(value).map(Source::Persistent)
+        } else if value.get("type").and_then(Value::as_str) == Some("http") {
+            http::HttpSource::deserialize(value).map(Source::Http)
+        } else if value.get("type").is_some() {
+            BuiltInSource::deserialize(value).map(Source::BuiltIn)
+        } else {
+            QueryCommand::deserialize(value).map(Source::Command)
+        };
+        source.map_err(serde::de::Error::custom)
+    }
+}
+
+/// mirrors the [`Deserialize`] implementation
+impl JsonSchema for Source {
+    fn schema_name() -> Cow<'static, str> {
+        "Source".into()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        // the backends that aren't part of `BuiltInSource`, but have a `type`
+        let typed = |schema: Schema, source_type: &str| {
+            json_schema!({
+                "allOf": [
+                    schema,
+                    {
+                        "type": "object",
+                        "properties": { "type": { "const": source_type } },
+                        "required": ["type"]
+                    }
+                ]
+            })
+        };
+        let mut sources = vec![
+            generator.subschema_for::<QueryCommand>(),
+            typed(
+                generator.subschema_for::<persistent::PersistentSource>(),
+                "persistent",
+            ),
+            typed(generator.subschema_for::<http::HttpSource>(), "http"),
+        ];
+        #[cfg(unix)]
+        sources.push(typed(
+            generator.subschema_for::<socket::SocketSource>(),
+            "socket",
+        ));
+        sources.push(generator.subschema_for::<BuiltInSource>());
+        sources.push(json_schema!({
+            "type": "array",
+            "description": "several sources, whose results are merged",
+            "minItems": 1,
+            "items": {
+                "allOf": [
+                    generator.subschema_for::<Source>(),
+                    {
+                        "properties": {
+                            "name": {
+                                "type": "string",
+                                "description": "the name of the source shown above the results"
+                            }
+                        }
+                    }
+                ]
+            }
+        }));
+        json_schema!({ "anyOf": sources })
+    }
+}
+
+impl Source {
+    /// searches for `query`. sources streaming their results also send each
+    /// entry to `entries` as soon as it's read
+    pub async fn search(
+        &self,
+        query: String,
+        path: String,
+        limits: &Limits,
+        entries: UnboundedSender<SearchResultEntry>,
+    ) -> anyhow::Result<SearchResult> {
+        match self {
+            Source::Command(command) => command.search(query, path, limits, entries).await,
+            Source::Persistent(persistent) => persistent.search(&query, &path, limits).await,
+            Source::Http(http) => http.search(&query, &path, limits).await,
+            #[cfg(unix)]
+            Source::Socket(socket) => socket.search(&query, &path, limits).await,
+            Source::BuiltIn(source) => source.search(&query, limits).await,
+            Source::Merged(merged) => merged.search(query, path, limits, entries).await,
+        }
+    }
+
+    /// performs the source-specific action (if any) for the accepted entry
+    pub fn accept(&self, entry: &SearchResultEntry) -> anyhow::Result<()> {
+        match self {
+            Source::Command(_) | Source::Persistent(_) | Source::Http(_) => Ok(()),
+            #[cfg(unix)]
+            Source::Socket(_) => Ok(()),
+            Source::BuiltIn(source) => source.accept(entry),
+            Source::Merged(merged) => merged.accept(entry),
+        }
+    }
+}
+
+impl QueryCommand {
+    /// renders the executable and arguments using `context`. scripts get the
+    /// fields of `context` as environment variables instead
+    pub fn build<C: Serialize>(&self, context: &C) -> anyhow::Result<Command> {
+        let template = |template_string| Template::new(template_string)?.render(context);
+        let mut command = Command::new(template(&self.executable)?);
+        for arg in &self.args {
+            command.arg(template(arg)?);
+        }
+        if let Some(script) = &self.script {
+            command.arg("-c").arg(script);
+            if let Value::Object(fields) = serde_json::to_value(context)? {
+                set_env_vars(&mut command, "SEARCH_TUI", &fields);
+            }
+        }
+        Ok(command)
+    }
+
+    /// the command searching for `query`, with piped stdout and stderr, and
+    /// what to write to its stdin
+    fn search_command(&self, query: String, path: String) -> anyhow::Result<(Command, Vec<u8>)> {
+        #[derive(Serialize)]
+        struct Context {
+            query: String,
+            query_escaped: String,
+            path: String,
+        }
+
+        let context = Context {
+            query_escaped: query.escape_debug().to_string(),
+            query,
+            path,
+        };
+
+        let mut command = self.build(&context)?;
+        command
+            .stdin(match self.stdin {
+                QueryInput::None => Stdio::null(),
+                QueryInput::Query | QueryInput::Json => Stdio::piped(),
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let mut input = match self.stdin {
+            QueryInput::None => Vec::new(),
+            QueryInput::Query => context.query.into_bytes(),
+            QueryInput::Json => serde_json::to_vec(&serde_json::json!({
+                "query": context.query,
+                "path": context.path,
+            }))?,
+        };
+        if self.stdin != QueryInput::None {
+            input.push(b'\n');
+        }
+        Ok((command, input))
+    }
+
+    /// runs the search command, which is terminated if the search is
+    /// superseded (i.e. this future is dropped)
+    async fn search(
+        &self,
+        query: String,
+        path: String,
+        limits: &Limits,
+        entries: UnboundedSender<SearchResultEntry>,
+    ) -> anyhow::Result<SearchResult> {
+        let (command, input) = self.search_command(query, path)?;
+        let process = SearchProcess::spawn(command, input)?;
+        match self.format {
+            OutputFormat::Json => read_output(process, limits, json::parse).await,
+            OutputFormat::Msgpack => read_output(process, limits, json::parse_msgpack).await,
+            OutputFormat::Ndjson => {
+                read_lines(process, limits, &entries, |line| {
+                    Ok(serde_json::from_slice(line)?)
+                })
+                .await
+            }
+            OutputFormat::Delimited => {
+                read_lines(process, limits, &entries, |line| {
+                    delimited::parse_row(
+                        &String::from_utf8_lossy(line),
+                        self.delimiter,
+                        &self.columns,
+                    )
+                })
+                .await
+            }
+        }
+    }
+}
+
+/// sets `PREFIX_FIELD` to each scalar field of `fields`, and the fields of
+/// objects like `extra` as `PREFIX_EXTRA_FIELD`
+fn set_env_vars(command: &mut Command, prefix: &str, fields: &serde_json::Map<String, Value>) {
+    for (key, value) in fields {
+        let name = format!("{prefix}_{key}")
+            .to_uppercase()
+            .replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+        match value {
+            Value::String(string) => {
+                command.env(name, string);
+            }
+            Value::Number(_) | Value::Bool(_) => {
+                command.env(name, value.to_string());
+            }
+            Value::Object(fields) => set_env_vars(command, &name, fields),
+            Value::Null | Value::Array(_) => {}
+        }
+    }
+}
+
+/// reads the whole output of a search command, parsed by `parse`
+async fn read_output(
+    mut process: SearchProcess,
+    limits: &Limits,
+    parse: fn(&[u8], usize, bool) -> anyhow::Result<SearchResult>,
+) -> anyhow::Result<SearchResult> {
+    let mut stdout = Vec::new();
+    process
+        .stdout()
+        .take(limits.max_output_bytes + 1)
+        .read_to_end(&mut stdout)
+        .await?;
+    let truncated = stdout.len() as u64 > limits.max_output_bytes;
+    if truncated {
+        stdout.truncate(limits.max_output_bytes as usize);
+        // the backend is terminated when the process is dropped, so its exit
+        // status doesn't matter
+        drop(process);
+    } else {
+        process.wait().await?;
+    }
+
+    parse(&stdout, limits.max_results, truncated)
+}
+
+/// reads the output of a search command printing one entry per line, parsed
+/// by `parse_line`, sending the first `max_results` entries to `entries` as
+/// they are read
+async fn read_lines(
+    mut process: SearchProcess,
+    limits: &Limits,
+    entries: &UnboundedSender<SearchResultEntry>,
+    parse_line: impl Fn(&[u8]) -> anyhow::Result<SearchResultEntry>,
+) -> anyhow::Result<SearchResult> {
+    let mut stdout = BufReader::new(process.stdout().take(limits.max_output_bytes + 1));
+    let mut result = SearchResult::new(Vec::new());
+    let mut num_entries = 0;
+    let mut num_bytes = 0;
+    let mut line = Vec::new();
+    for line_number in 1.. {
+        line.clear();
+        let len = stdout.read_until(b'\n', &mut line).await?;
+        if len == 0 {
+            break;
+        }
+        num_bytes += len as u64;
+        if num_bytes > limits.max_output_bytes {
+            result.warnings.push(format!(
+                "backend output truncated, showing the first {} entries",
+                result.results.len()
+            ));
+            return Ok(result);
+        }
+        if line.trim_ascii().is_empty() {
+            continue;
+        }
+        num_entries += 1;
+        if num_entries > limits.max_results {
+            continue;
+        }
+        let entry = parse_line(&line).map_err(|err| {
+            anyhow::anyhow!("unable to parse line {line_number} of the backend output: {err}")
+        })?;
+        // sending fails if the search has been superseded, in which case the
+        // command is being terminated
+        let _ = entries.unbounded_send(entry.clone());
+        result.results.push(entry);
+    }
+
+    process.wait().await?;
+    if num_entries > limits.max_results {
+        result.warnings.push(format!(
+            "showing the first {} of {num_entries} entries",
+            limits.max_results
+        ));
+    }
+    Ok(result)
+}
+
+impl BuiltInSource {
+    async fn search(&self, query: &str, limits: &Limits) -> anyhow::Result<SearchResult> {
+        match self {
+            #[cfg(feature = "clipboard")]
+            BuiltInSource::Clipboard(clipboard) => clipboard.search(query),
+            BuiltInSource::File(file) => file.search(query),
+            BuiltInSource::SshHosts(ssh_hosts) => ssh_hosts.search(query),
+            BuiltInSource::Processes(processes) => processes.search(query).await,
+            BuiltInSource::Stdin(stdin) => stdin.search(query),
+            BuiltInSource::WebSuggestions(web) => web.search(query, limits).await,
+        }
+    }
+
+    #[cfg_attr(not(feature = "clipboard"), allow(unused_variables))]
+    fn accept(&self, entry: &SearchResultEntry) -> anyhow::Result<()> {
+        match self {
+            #[cfg(feature = "clipboard")]
+            BuiltInSource::Clipboard(clipboard) => clipboard.copy(&entry.identifier),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! a clipboard history, recorded by running `search-tui --clipboard-daemon`
+//! with the same config
+
+use std::{
+    collections::HashSet,
+    env,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, ErrorKind, Write},
+    path::PathBuf,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::QueryCommand;
+use crate::{
+    action::{copy_to_clipboard, is_wayland, pipe_to},
+    filter::filter,
+    SearchResult, SearchResultEntry,
+};
+
+#[derive(Deserialize, JsonSchema)]
+pub struct ClipboardSource {
+    /// defaults to `$XDG_DATA_HOME/search-tui/clipboard.jsonl`
+    history_file: Option<PathBuf>,
+    /// prints the clipboard content, defaults to `wl-paste` or `xclip`
+    paste_command: Option<QueryCommand>,
+    /// reads the new clipboard content from stdin, defaults to `wl-copy` or
+    /// `xclip`
+    copy_command: Option<QueryCommand>,
+    #[serde(default = "default_poll_millis")]
+    poll_millis: u64,
+    #[serde(default = "default_max_entries")]
+    max_entries: usize,
+}
+
+fn default_poll_millis() -> u64 {
+    500
+}
+
+fn default_max_entries() -> usize {
+    1000
+}
+
+#[derive(Serialize, Deserialize)]
+struct Clip {
+    timestamp: u64,
+    text: String,
+}
+
+impl ClipboardSource {
+    pub fn search(&self, query: &str) -> anyhow::Result<SearchResult> {
+        let entries = self
+            .load()?
+            .into_iter()
+            .rev()
+            .map(|clip| SearchResultEntry {
+                confidence: 1.0,
+                identifier: clip.text.clone(),
+                title: clip.text,
+                extra: Map::from_iter([("timestamp".to_owned(), Value::from(clip.timestamp))]),
+            });
+        Ok(filter(entries, query))
+    }
+
+    /// puts `text` back into the clipboard
+    pub fn copy(&self, text: &str) -> anyhow::Result<()> {
+        match &self.copy_command {
+            Some(command) => {
+                let mut copy_command = Command::new(&command.executable);
+                copy_command.args(&command.args);
+                pipe_to(copy_command, text)
+            }
+            None => copy_to_clipboard(text),
+        }
+    }
+
+    /// polls the clipboard forever, appending every new content to the
+    /// history file
+    pub fn run_daemon(&self) -> anyhow::Result<()> {
+        let path = self.history_file()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // compact the history first, since it is only ever appended to
+        let clips = self.load()?;
+        let mut last_text = clips.last().map(|clip| clip.text.clone());
+        let mut file = File::create(&path)?;
+        for clip in &clips {
+            writeln!(file, "{}", serde_json::to_string(clip)?)?;
+        }
+        drop(file);
+
+        let mut file = OpenOptions::new().append(true).open(&path)?;
+        loop {
+            // the clipboard is often empty or holding non-text data, which
+            // makes the paste command fail
+            if let Some(text) = self.paste().filter(|text| !text.is_empty()) {
+                if last_text.as_ref() != Some(&text) {
+                    let clip = Clip {
+                        timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+                        text,
+                    };
+                    writeln!(file, "{}", serde_json::to_string(&clip)?)?;
+                    last_text = Some(clip.text);
+                }
+            }
+
+            thread::sleep(Duration::from_millis(self.poll_millis));
+        }
+    }
+
+    fn paste(&self) -> Option<String> {
+        let (executable, args) = match &self.paste_command {
+            Some(command) => (command.executable.as_str(), command.args.clone()),
+            None if is_wayland() => ("wl-paste", vec!["--no-newline".into()]),
+            None => (
+                "xclip",
+                vec!["-selection".into(), "clipboard".into(), "-o".into()],
+            ),
+        };
+
+        let output = Command::new(executable)
+            .args(args)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        output.status.success().then_some(())?;
+        String::from_utf8(output.stdout).ok()
+    }
+
+    /// loads the deduplicated history, oldest clip first
+    fn load(&self) -> anyhow::Result<Vec<Clip>> {
+        let file = match File::open(self.history_file()?) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut seen = HashSet::new();
+        let mut clips = Vec::new();
+        let lines = BufReader::new(file)
+            .lines()
+            .collect::<Result<Vec<_>, _>>()?;
+        for line in lines.iter().rev() {
+            let clip: Clip = serde_json::from_str(line).context("corrupted clipboard history")?;
+            if seen.insert(clip.text.clone()) {
+                clips.push(clip);
+                if clips.len() >= self.max_entries {
+                    break;
+                }
+            }
+        }
+
+        clips.reverse();
+        Ok(clips)
+    }
+
+    fn history_file(&self) -> anyhow::Result<PathBuf> {
+        if let Some(path) = &self.history_file {
+            return Ok(path.clone());
+        }
+
+        env::var_os("XDG_DATA_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+            .map(|dir| dir.join("search-tui/clipboard.jsonl"))
+            .context("unable to determine the clipboard history location")
+    }
+}
@@ -0,0 +1,77 @@
+//! delimiter-separated rows, like the tsv printed by lots of tools, mapped to
+//! entries by naming their columns
+
+use serde_json::{Map, Value};
+
+use crate::SearchResultEntry;
+
+/// splits `line` at `delimiter`. fields starting with `"` are quoted, with
+/// `""` escaping a quote, so that they can contain the delimiter
+pub fn split_row(line: &str, delimiter: char) -> anyhow::Result<Vec<String>> {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    anyhow::ensure!(!quoted, "unterminated quoted field");
+    fields.push(field);
+    Ok(fields)
+}
+
+/// the entry whose fields are named by `columns`. empty column names skip
+/// their column, and so do missing names of trailing columns
+pub fn parse_row(
+    line: &str,
+    delimiter: char,
+    columns: &[String],
+) -> anyhow::Result<SearchResultEntry> {
+    let fields = columns
+        .iter()
+        .zip(split_row(line, delimiter)?)
+        .filter(|(column, _)| !column.is_empty())
+        .map(|(column, field)| (column.clone(), Value::from(field)))
+        .collect();
+    to_entry(fields)
+}
+
+/// the entry with the `identifier`, `title` and `confidence` of `fields`, and
+/// the other fields as extra fields. either of `identifier` and `title`
+/// defaults to the other, and `confidence` to 1
+pub fn to_entry(mut fields: Map<String, Value>) -> anyhow::Result<SearchResultEntry> {
+    let mut take = |field: &str| match fields.remove(field) {
+        Some(Value::String(value)) => Ok(Some(value)),
+        Some(Value::Null) | None => Ok(None),
+        Some(_) => Err(anyhow::anyhow!("{field} isn't a string")),
+    };
+    let (identifier, title) = match (take("identifier")?, take("title")?) {
+        (Some(identifier), Some(title)) => (identifier, title),
+        (Some(text), None) | (None, Some(text)) => (text.clone(), text),
+        (None, None) => anyhow::bail!("missing both the identifier and title"),
+    };
+    let confidence = match fields.remove("confidence") {
+        Some(Value::Number(confidence)) => confidence.as_f64(),
+        Some(Value::String(confidence)) => confidence.trim().parse().ok(),
+        Some(Value::Null) | None => Some(1.0),
+        Some(_) => None,
+    }
+    .ok_or_else(|| anyhow::anyhow!("confidence isn't a number"))?;
+    Ok(SearchResultEntry {
+        confidence,
+        identifier,
+        title,
+        extra: fields,
+    })
+}
@@ -0,0 +1,172 @@
+//! entries loaded from a json or csv file at startup and filtered in-process,
+//! for fixed lists like emoji or hosts that don't need a backend
+
+use std::{borrow::Cow, fs, path::Path};
+
+use anyhow::Context;
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use super::delimited::{split_row, to_entry};
+use crate::{filter::filter_by, template, SearchResult, SearchResultEntry};
+
+/// [`FileSourceConfig`] with the entries loaded
+#[derive(Deserialize)]
+#[serde(try_from = "FileSourceConfig")]
+pub struct FileSource {
+    entries: Vec<Entry>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct FileSourceConfig {
+    /// the file the entries are read from, with the `identifier` and `title`
+    /// fields (either of which defaults to the other) and any other field of
+    /// the entries
+    #[serde(deserialize_with = "template::deserialize_expanded")]
+    path: String,
+    /// inferred from the extension of `path`, json unless it's `.csv`
+    format: Option<FileFormat>,
+    /// the fields matched against the query, joined with spaces. defaults to
+    /// the title
+    match_fields: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum FileFormat {
+    /// an array of entries, or of strings which are both the identifier and
+    /// title of their entry
+    Json,
+    /// a header row naming the fields, then one entry per row
+    Csv,
+}
+
+struct Entry {
+    entry: SearchResultEntry,
+    /// the part of the entry matched against the query
+    match_text: String,
+}
+
+impl TryFrom<FileSourceConfig> for FileSource {
+    type Error = String;
+
+    fn try_from(config: FileSourceConfig) -> Result<Self, String> {
+        Self::load(config).map_err(|err| format!("{err:#}"))
+    }
+}
+
+impl FileSource {
+    fn load(config: FileSourceConfig) -> anyhow::Result<Self> {
+        let path = Path::new(&config.path);
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("unable to read {}", path.display()))?;
+        let format = config.format.unwrap_or_else(|| {
+            match path.extension().and_then(|extension| extension.to_str()) {
+                Some(extension) if extension.eq_ignore_ascii_case("csv") => FileFormat::Csv,
+                _ => FileFormat::Json,
+            }
+        });
+        let records = match format {
+            FileFormat::Json => parse_json(&content),
+            FileFormat::Csv => parse_csv(&content),
+        }
+        .with_context(|| format!("unable to parse {}", path.display()))?;
+
+        let entries = records
+            .into_iter()
+            .enumerate()
+            .map(|(index, record)| {
+                let entry = to_entry(record)
+                    .with_context(|| format!("entry {} of {}", index + 1, path.display()))?;
+                let match_text = match &config.match_fields {
+                    Some(fields) => match_text(&entry, fields),
+                    None => entry.title.clone(),
+                };
+                Ok(Entry { entry, match_text })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self { entries })
+    }
+
+    pub fn search(&self, query: &str) -> anyhow::Result<SearchResult> {
+        let entries = self
+            .entries
+            .iter()
+            .map(|entry| (entry.entry.clone(), entry.match_text.clone()));
+        Ok(filter_by(entries, query))
+    }
+}
+
+impl JsonSchema for FileSource {
+    fn schema_name() -> Cow<'static, str> {
+        "FileSource".into()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        FileSourceConfig::json_schema(generator)
+    }
+}
+
+fn parse_json(content: &str) -> anyhow::Result<Vec<Map<String, Value>>> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Record {
+        Entry(Map<String, Value>),
+        Text(String),
+    }
+
+    let records: Vec<Record> = serde_json::from_str(content)?;
+    Ok(records
+        .into_iter()
+        .map(|record| match record {
+            Record::Entry(entry) => entry,
+            Record::Text(text) => Map::from_iter([("identifier".to_owned(), text.into())]),
+        })
+        .collect())
+}
+
+/// parses csv with a header row naming the fields
+fn parse_csv(content: &str) -> anyhow::Result<Vec<Map<String, Value>>> {
+    let mut lines = content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    let (_, header) = lines.next().context("missing the header row")?;
+    let header = split_row(header, ',')?;
+    lines
+        .map(|(index, line)| {
+            let row =
+                split_row(line, ',').with_context(|| format!("invalid line {}", index + 1))?;
+            anyhow::ensure!(
+                row.len() == header.len(),
+                "line {} has {} fields, but the header has {}",
+                index + 1,
+                row.len(),
+                header.len()
+            );
+            Ok(header
+                .iter()
+                .cloned()
+                .zip(row.into_iter().map(Value::from))
+                .collect())
+        })
+        .collect()
+}
+
+/// the `fields` of `entry`, with non-string extra fields as json
+fn match_text(entry: &SearchResultEntry, fields: &[String]) -> String {
+    let texts: Vec<String> = fields
+        .iter()
+        .filter_map(|field| match field.as_str() {
+            "identifier" => Some(entry.identifier.clone()),
+            "title" => Some(entry.title.clone()),
+            field => match entry.extra.get(field)? {
+                Value::String(text) => Some(text.clone()),
+                Value::Null => None,
+                value => Some(value.to_string()),
+            },
+        })
+        .collect();
+    texts.join(" ")
+}
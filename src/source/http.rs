@@ -0,0 +1,106 @@
+//! a backend answering searches over http, which avoids spawning a process
+//! (like `curl`) for every query of api-backed searches
+
+use std::collections::BTreeMap;
+
+use anyhow::Context;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{json, web::client};
+use crate::{
+    template::{self, Template},
+    Limits, SearchResult,
+};
+
+#[derive(Deserialize, JsonSchema)]
+pub struct HttpSource {
+    /// a template of the url, with the `{query}` and `{path}` template
+    /// variables, e.g. `https://example.com/search?q={query | url}`
+    #[serde(deserialize_with = "template::deserialize_expanded")]
+    pub url: String,
+    #[serde(default)]
+    method: HttpMethod,
+    /// a template of the body of post requests, which is a json object with
+    /// the `query` and `path` by default
+    pub body: Option<String>,
+    /// the request headers, whose values are templates, e.g.
+    /// `"Authorization": "Bearer {env.API_TOKEN}"`
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, JsonSchema, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum HttpMethod {
+    #[default]
+    Get,
+    Post,
+}
+
+impl HttpSource {
+    /// sends the request, whose response body is a json [`SearchResult`]
+    pub async fn search(
+        &self,
+        query: &str,
+        path: &str,
+        limits: &Limits,
+    ) -> anyhow::Result<SearchResult> {
+        #[derive(Serialize)]
+        struct Context<'a> {
+            query: &'a str,
+            path: &'a str,
+        }
+
+        let context = Context { query, path };
+        let url = Template::new(&self.url)?.render(&context)?;
+        let mut request = match self.method {
+            HttpMethod::Get => client().get(&url),
+            HttpMethod::Post => {
+                let body = match &self.body {
+                    Some(body) => Template::new(body)?.render(&context)?,
+                    None => serde_json::to_string(&context)?,
+                };
+                client()
+                    .post(&url)
+                    .header("Content-Type", "application/json")
+                    .body(body)
+            }
+        };
+        for (name, value) in &self.headers {
+            request = request.header(name, Template::new(value)?.render(&context)?);
+        }
+
+        let mut response = request
+            .send()
+            .await
+            .with_context(|| format!("unable to search {url}"))?;
+        let status = response.status();
+        let (body, truncated) = read_body(&mut response, limits).await?;
+        if !status.is_success() {
+            let body = String::from_utf8_lossy(&body);
+            anyhow::bail!("{url} responded with {status}: {}", body.trim());
+        }
+        json::parse(&body, limits.max_results, truncated)
+    }
+}
+
+/// reads at most `limits.max_output_bytes` of the response body, returning
+/// whether it was truncated
+pub async fn read_body(
+    response: &mut reqwest::Response,
+    limits: &Limits,
+) -> anyhow::Result<(Vec<u8>, bool)> {
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() as u64 > limits.max_output_bytes {
+            break;
+        }
+    }
+    let truncated = body.len() as u64 > limits.max_output_bytes;
+    if truncated {
+        body.truncate(limits.max_output_bytes as usize);
+    }
+    Ok((body, truncated))
+}
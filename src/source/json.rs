@@ -0,0 +1,169 @@
+//! bounded parsing of the [`SearchResult`] format, in json or messagepack, so
+//! that a misbehaving backend can't make search-tui run out of memory
+
+use std::fmt;
+
+use serde::{
+    de::{DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor},
+    Deserializer,
+};
+
+use crate::{SearchResult, SearchResultEntry};
+
+/// parses a json search result, keeping at most `max_results` entries. if
+/// `truncated` is set, `output` is only a prefix of the backend output, and the
+/// entries before the cut are kept.
+pub fn parse(output: &[u8], max_results: usize, truncated: bool) -> anyhow::Result<SearchResult> {
+    let mut deserializer = serde_json::Deserializer::from_slice(output);
+    let (parse_result, mut result, num_entries) = parse_with(&mut deserializer, max_results);
+    match parse_result {
+        Ok(()) => deserializer.end()?,
+        Err(err) if truncated && err.is_eof() => {
+            result.warnings.push(truncated_warning(&result));
+            return Ok(result);
+        }
+        Err(err) => return Err(err.into()),
+    }
+    Ok(with_count_warning(result, num_entries, max_results))
+}
+
+/// like [`parse`], but for the messagepack encoding of the same format, which
+/// is faster to parse for backends printing lots of entries
+pub fn parse_msgpack(
+    output: &[u8],
+    max_results: usize,
+    truncated: bool,
+) -> anyhow::Result<SearchResult> {
+    let mut deserializer = rmp_serde::Deserializer::from_read_ref(output);
+    let (parse_result, mut result, num_entries) = parse_with(&mut deserializer, max_results);
+    match parse_result {
+        Ok(()) => {}
+        // messagepack errors don't tell whether the input ended early
+        Err(_) if truncated => {
+            result.warnings.push(truncated_warning(&result));
+            return Ok(result);
+        }
+        Err(err) => return Err(err.into()),
+    }
+    Ok(with_count_warning(result, num_entries, max_results))
+}
+
+/// parses a search result with `deserializer`, returning the entries parsed
+/// before an error along with the number of entries in the output
+fn parse_with<'de, D: Deserializer<'de>>(
+    deserializer: D,
+    max_results: usize,
+) -> (Result<(), D::Error>, SearchResult, usize) {
+    let mut entries = Vec::new();
+    let mut num_entries = 0;
+    let mut suggested_query = None;
+    let parse_result = deserializer.deserialize_map(ResultVisitor {
+        entries: &mut entries,
+        num_entries: &mut num_entries,
+        suggested_query: &mut suggested_query,
+        max_results,
+    });
+    let mut result = SearchResult::new(entries);
+    result.suggested_query = suggested_query;
+    (parse_result, result, num_entries)
+}
+
+fn truncated_warning(result: &SearchResult) -> String {
+    format!(
+        "backend output truncated, showing the first {} entries",
+        result.results.len()
+    )
+}
+
+fn with_count_warning(
+    mut result: SearchResult,
+    num_entries: usize,
+    max_results: usize,
+) -> SearchResult {
+    if num_entries > max_results {
+        result.warnings.push(format!(
+            "showing the first {max_results} of {num_entries} entries"
+        ));
+    }
+    result
+}
+
+/// visits the top-level object, collecting the entries of `results`
+struct ResultVisitor<'a> {
+    entries: &'a mut Vec<SearchResultEntry>,
+    num_entries: &'a mut usize,
+    suggested_query: &'a mut Option<String>,
+    max_results: usize,
+}
+
+impl<'de> Visitor<'de> for ResultVisitor<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a search result object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let mut has_results = false;
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "results" && !has_results {
+                has_results = true;
+                map.next_value_seed(EntriesSeed {
+                    entries: &mut *self.entries,
+                    num_entries: &mut *self.num_entries,
+                    max_results: self.max_results,
+                })?;
+            } else if key == "suggested_query" {
+                *self.suggested_query = map.next_value()?;
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+
+        if !has_results {
+            return Err(serde::de::Error::missing_field("results"));
+        }
+        Ok(())
+    }
+}
+
+/// collects the first `max_results` entries of the `results` array, only
+/// counting the others
+struct EntriesSeed<'a> {
+    entries: &'a mut Vec<SearchResultEntry>,
+    num_entries: &'a mut usize,
+    max_results: usize,
+}
+
+impl<'de> DeserializeSeed<'de> for EntriesSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for EntriesSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array of entries")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while *self.num_entries < self.max_results {
+            match seq.next_element::<SearchResultEntry>()? {
+                Some(entry) => {
+                    self.entries.push(entry);
+                    *self.num_entries += 1;
+                }
+                None => return Ok(()),
+            }
+        }
+
+        while seq.next_element::<IgnoredAny>()?.is_some() {
+            *self.num_entries += 1;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+//! several sources searched concurrently, whose results are merged into a
+//! single list, e.g. apps, files and bookmarks in one launcher
+
+use std::{future, path::Path};
+
+use anyhow::Context;
+use futures::{channel::mpsc, future::join_all, StreamExt};
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{BuiltInSource, Source};
+use crate::{Limits, SearchResult, SearchResultEntry};
+
+pub struct MergedSource {
+    pub sources: Vec<NamedSource>,
+}
+
+pub struct NamedSource {
+    /// the `source` of the entries of this source, unless they have one
+    pub name: String,
+    pub source: Source,
+}
+
+impl MergedSource {
+    /// deserializes the configs of the sources, named by their `name`
+    /// property, or else by their executable or `type`
+    pub fn from_values(values: Vec<Value>) -> Result<Self, serde_json::Error> {
+        if values.is_empty() {
+            return Err(serde::de::Error::invalid_length(0, &"at least one source"));
+        }
+        let sources = values
+            .into_iter()
+            .enumerate()
+            .map(|(index, value)| {
+                let name = value
+                    .get("name")
+                    .or_else(|| value.get("executable"))
+                    .or_else(|| value.get("type"))
+                    .and_then(Value::as_str)
+                    .and_then(|name| Path::new(name).file_name()?.to_str())
+                    .map_or_else(|| format!("source {}", index + 1), str::to_string);
+                match Source::deserialize(value)? {
+                    Source::BuiltIn(BuiltInSource::Stdin(_)) => Err(serde::de::Error::custom(
+                        "stdin sources can't be merged with other sources",
+                    )),
+                    source => Ok(NamedSource { name, source }),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { sources })
+    }
+
+    /// searches all sources concurrently, failing only if all of them fail.
+    /// the errors of the others are shown as warnings
+    pub async fn search(
+        &self,
+        query: String,
+        path: String,
+        limits: &Limits,
+        entries: mpsc::UnboundedSender<SearchResultEntry>,
+    ) -> anyhow::Result<SearchResult> {
+        let searches = self.sources.iter().map(|source| {
+            let (sender, source_entries) = mpsc::unbounded();
+            let forward = source_entries.for_each(|mut entry| {
+                source.tag(&mut entry);
+                let _ = entries.unbounded_send(entry);
+                future::ready(())
+            });
+            let search = Box::pin(source.source.search(
+                query.clone(),
+                path.clone(),
+                limits,
+                sender,
+            ));
+            async move {
+                let (result, ()) = futures::join!(search, forward);
+                result.with_context(|| format!("{} failed", source.name))
+            }
+        });
+
+        let mut merged = SearchResult::new(Vec::new());
+        let mut errors = Vec::new();
+        for (source, result) in self.sources.iter().zip(join_all(searches).await) {
+            match result {
+                Ok(result) => {
+                    merged
+                        .results
+                        .extend(result.results.into_iter().map(|mut entry| {
+                            source.tag(&mut entry);
+                            entry
+                        }));
+                    merged.suggested_query = merged.suggested_query.or(result.suggested_query);
+                    merged.warnings.extend(
+                        result
+                            .warnings
+                            .into_iter()
+                            .map(|warning| format!("{}: {warning}", source.name)),
+                    );
+                }
+                Err(err) => errors.push(format!("{err:#}")),
+            }
+        }
+        if errors.len() == self.sources.len() {
+            anyhow::bail!("{}", errors.join(", "));
+        }
+        merged.warnings.extend(errors);
+        Ok(merged)
+    }
+
+    /// performs the action of the source of the accepted entry
+    pub fn accept(&self, entry: &SearchResultEntry) -> anyhow::Result<()> {
+        let source = self
+            .sources
+            .iter()
+            .find(|source| entry.source() == Some(source.name.as_str()));
+        match source {
+            Some(source) => source.source.accept(entry),
+            None => Ok(()),
+        }
+    }
+}
+
+impl NamedSource {
+    fn tag(&self, entry: &mut SearchResultEntry) {
+        entry
+            .extra
+            .entry("source")
+            .or_insert_with(|| self.name.clone().into());
+    }
+}
@@ -0,0 +1,303 @@
+//! a long-lived backend process, which answers search requests sent as json
+//! lines on its stdin with json lines on its stdout, so that expensive setup
+//! (loading an index, connecting to a database, etc.) only happens once
+
+use std::{process::Stdio, time::Duration};
+
+use anyhow::Context;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    process::{Child, ChildStdin, ChildStdout, Command},
+    sync::Mutex,
+};
+
+use super::json;
+use crate::{template::expand_env, Limits, SearchResult};
+
+/// the latest protocol version understood by search-tui
+const PROTOCOL_VERSION: u32 = 1;
+
+/// the longest hello message read, since the limits only apply to answers
+const MAX_HELLO_BYTES: u64 = 64 * 1024;
+
+/// the optional protocol features supported by search-tui. answers are read
+/// as a single line, so streaming isn't supported, and accepting and
+/// previewing entries is configured with `accept_action` and `preview` instead
+/// of being left to the backend
+const CLIENT_CAPABILITIES: &[Capability] = &[Capability::Pagination];
+
+#[derive(Deserialize, JsonSchema)]
+pub struct PersistentSource {
+    pub executable: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// how long to wait for the backend to answer the handshake before
+    /// treating it as a version 0 backend
+    #[serde(default = "default_handshake_timeout_millis")]
+    handshake_timeout_millis: u64,
+    /// how long to wait for the answer to a search before restarting the
+    /// backend
+    #[serde(default = "default_response_timeout_millis")]
+    response_timeout_millis: u64,
+    #[serde(skip)]
+    backend: Mutex<Option<Box<Backend>>>,
+}
+
+fn default_handshake_timeout_millis() -> u64 {
+    1000
+}
+
+fn default_response_timeout_millis() -> u64 {
+    30_000
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Capability {
+    /// results are sent in several batches
+    Streaming,
+    /// the number of results sent can be limited
+    Pagination,
+    /// the backend handles accepted entries itself
+    Actions,
+    /// the backend renders entry previews
+    Previews,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Request<'a> {
+    Hello {
+        version: u32,
+        capabilities: &'a [Capability],
+    },
+    Search {
+        query: &'a str,
+        path: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        limit: Option<usize>,
+    },
+}
+
+#[derive(Deserialize)]
+struct HelloResponse {
+    version: u32,
+    #[serde(default)]
+    capabilities: Vec<Value>,
+}
+
+struct Backend {
+    /// killed when the backend is dropped
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    /// the start of the line being read, kept if reading it is abandoned
+    line: Vec<u8>,
+    /// whether the rest of a line that is too long is being skipped
+    overflowed: bool,
+    /// whether a request was sent without its answer being read, e.g. because
+    /// the search was abandoned, in which case that answer is skipped before
+    /// the next request
+    pending: bool,
+    version: u32,
+    /// the capabilities supported by both search-tui and the backend
+    capabilities: Vec<Capability>,
+}
+
+impl PersistentSource {
+    pub async fn search(
+        &self,
+        query: &str,
+        path: &str,
+        limits: &Limits,
+    ) -> anyhow::Result<SearchResult> {
+        let mut backend = self.backend.lock().await;
+        if backend.is_none() {
+            *backend = Some(Box::new(self.spawn().await?));
+        }
+
+        let timeout = Duration::from_millis(self.response_timeout_millis);
+        let result = backend
+            .as_mut()
+            .expect("backend is running")
+            .search(query, path, limits, timeout)
+            .await;
+        if result.is_err() {
+            // the backend is restarted on the next search, in case it crashed
+            *backend = None;
+        }
+        result
+    }
+
+    async fn spawn(&self) -> anyhow::Result<Backend> {
+        let mut child = Command::new(&*expand_env(&self.executable, false))
+            .args(
+                self.args
+                    .iter()
+                    .map(|arg| expand_env(arg, false).into_owned()),
+            )
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("unable to start backend {}", self.executable))?;
+        let stdin = child.stdin.take().expect("child stdin is piped");
+        let stdout = child.stdout.take().expect("child stdout is piped");
+        let mut backend = Backend {
+            _child: child,
+            stdin,
+            stdout: BufReader::new(stdout),
+            line: Vec::new(),
+            overflowed: false,
+            pending: false,
+            version: 0,
+            capabilities: Vec::new(),
+        };
+        backend
+            .handshake(Duration::from_millis(self.handshake_timeout_millis))
+            .await?;
+        Ok(backend)
+    }
+}
+
+impl Backend {
+    /// negotiates the protocol version and capabilities. backends that don't
+    /// answer with a hello message (in time) are treated as version 0, which
+    /// only supports plain search requests
+    async fn handshake(&mut self, timeout: Duration) -> anyhow::Result<()> {
+        self.send(&Request::Hello {
+            version: PROTOCOL_VERSION,
+            capabilities: CLIENT_CAPABILITIES,
+        })
+        .await?;
+        let line = match tokio::time::timeout(timeout, self.read_line(MAX_HELLO_BYTES)).await {
+            Ok(line) => line.context("backend exited during the handshake")?.0,
+            Err(_) => return Ok(()),
+        };
+        let Ok(hello) = serde_json::from_slice::<HelloResponse>(&line) else {
+            return Ok(());
+        };
+
+        // the backend may support a newer version, in which case it is
+        // expected to fall back to ours
+        self.version = hello.version.min(PROTOCOL_VERSION);
+        self.capabilities = hello
+            .capabilities
+            .into_iter()
+            .filter_map(|capability| Capability::deserialize(capability).ok())
+            .filter(|capability| CLIENT_CAPABILITIES.contains(capability))
+            .collect();
+        Ok(())
+    }
+
+    async fn search(
+        &mut self,
+        query: &str,
+        path: &str,
+        limits: &Limits,
+        timeout: Duration,
+    ) -> anyhow::Result<SearchResult> {
+        let limit = (self.version >= 1 && self.capabilities.contains(&Capability::Pagination))
+            .then_some(limits.max_results);
+        let timed_out = || anyhow::anyhow!("backend didn't answer within {timeout:?}");
+        if self.pending {
+            // the answer to an abandoned search, which may still be running
+            tokio::time::timeout(timeout, self.answer(limits))
+                .await
+                .map_err(|_| timed_out())??;
+            self.pending = false;
+        }
+        self.send(&Request::Search { query, path, limit }).await?;
+        self.pending = true;
+        let (line, truncated) = tokio::time::timeout(timeout, self.answer(limits))
+            .await
+            .map_err(|_| timed_out())??;
+        self.pending = false;
+
+        if let Ok(Value::Object(response)) = serde_json::from_slice::<Value>(&line) {
+            if let Some(error) = response.get("error").and_then(Value::as_str) {
+                return Err(anyhow::anyhow!("backend error: {error}"));
+            }
+        }
+
+        json::parse(&line, limits.max_results, truncated)
+    }
+
+    /// the next line that isn't a hello, which may arrive after the handshake
+    /// timed out
+    async fn answer(&mut self, limits: &Limits) -> anyhow::Result<(Vec<u8>, bool)> {
+        loop {
+            let (line, truncated) = self.read_line(limits.max_output_bytes).await?;
+            let is_hello = serde_json::from_slice::<Value>(&line)
+                .is_ok_and(|message| message.get("type") == Some(&Value::from("hello")));
+            if !is_hello {
+                return Ok((line, truncated));
+            }
+        }
+    }
+
+    /// reads the next line, without its newline, returning whether it was cut
+    /// off after `max_bytes`. the rest of a longer line is skipped without
+    /// keeping it in memory, and a read that is abandoned continues where it
+    /// left off
+    async fn read_line(&mut self, max_bytes: u64) -> anyhow::Result<(Vec<u8>, bool)> {
+        const SKIPPED_CHUNK_BYTES: u64 = 64 * 1024;
+
+        loop {
+            if self.overflowed {
+                let mut skipped = Vec::new();
+                let read = (&mut self.stdout)
+                    .take(SKIPPED_CHUNK_BYTES)
+                    .read_until(b'\n', &mut skipped)
+                    .await?;
+                if read == 0 {
+                    anyhow::bail!("backend exited");
+                }
+                if skipped.ends_with(b"\n") {
+                    self.overflowed = false;
+                    let mut line = std::mem::take(&mut self.line);
+                    line.truncate(max_bytes as usize);
+                    return Ok((line, true));
+                }
+                continue;
+            }
+
+            // one more byte than allowed tells a line that is too long apart
+            // from one that just fits
+            let remaining = max_bytes
+                .saturating_add(1)
+                .saturating_sub(self.line.len() as u64);
+            let read = (&mut self.stdout)
+                .take(remaining)
+                .read_until(b'\n', &mut self.line)
+                .await?;
+            if self.line.ends_with(b"\n") {
+                let mut line = std::mem::take(&mut self.line);
+                line.pop();
+                return Ok((line, false));
+            }
+            if self.line.len() as u64 > max_bytes {
+                self.overflowed = true;
+            } else if read == 0 {
+                anyhow::bail!("backend exited");
+            }
+        }
+    }
+
+    async fn send(&mut self, request: &Request<'_>) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(request)?;
+        line.push(b'\n');
+        self.stdin
+            .write_all(&line)
+            .await
+            .context("unable to send request to backend")?;
+        self.stdin
+            .flush()
+            .await
+            .context("unable to send request to backend")
+    }
+}
@@ -0,0 +1,109 @@
+//! the processes of search commands, which are terminated when their search is
+//! superseded so that expensive backends don't pile up
+
+use std::{io, process::Command, time::Duration};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    process::{Child, ChildStdout},
+    task::JoinHandle,
+};
+
+/// how long a superseded search command has to exit after SIGTERM before it's
+/// killed with SIGKILL
+const KILL_DELAY: Duration = Duration::from_millis(500);
+
+/// a running search command, which is terminated if it's dropped before it
+/// has exited, i.e. when the search is superseded
+pub struct SearchProcess {
+    child: Option<Child>,
+    stderr_reader: Option<JoinHandle<io::Result<Vec<u8>>>>,
+}
+
+impl SearchProcess {
+    /// spawns `command` in its own process group, so that the pipelines of
+    /// shell commands are terminated as a whole. `input` is written to its
+    /// stdin if it's piped
+    pub fn spawn(mut command: Command, input: Vec<u8>) -> anyhow::Result<Self> {
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        let mut child = tokio::process::Command::from(command)
+            .kill_on_drop(true)
+            .spawn()?;
+        // stdin is written and stderr is read concurrently, so that the child
+        // can't block on either of them
+        if let Some(mut child_stdin) = child.stdin.take() {
+            tokio::spawn(async move {
+                // the child may exit without reading its stdin
+                let _ = child_stdin.write_all(&input).await;
+            });
+        }
+        let mut child_stderr = child.stderr.take().expect("child stderr is piped");
+        let stderr_reader = tokio::spawn(async move {
+            let mut stderr = Vec::new();
+            child_stderr.read_to_end(&mut stderr).await.map(|_| stderr)
+        });
+        Ok(Self {
+            child: Some(child),
+            stderr_reader: Some(stderr_reader),
+        })
+    }
+
+    pub fn stdout(&mut self) -> ChildStdout {
+        let child = self.child.as_mut().expect("child is running");
+        child.stdout.take().expect("child stdout is piped")
+    }
+
+    /// waits for the command, failing with its stderr if it failed
+    pub async fn wait(mut self) -> anyhow::Result<()> {
+        let child = self.child.as_mut().expect("child is running");
+        let status = child.wait().await?;
+        let stderr_reader = self.stderr_reader.take().expect("stderr is read once");
+        let stderr = stderr_reader.await.expect("stderr reader panicked")?;
+        status.exit_ok().map_err(|err| {
+            let error = std::str::from_utf8(&stderr).unwrap_or("unable to decode stderr as utf-8");
+            anyhow::anyhow!("{error}, status error {err}")
+        })
+    }
+}
+
+impl Drop for SearchProcess {
+    fn drop(&mut self) {
+        let Some(mut child) = self.child.take() else {
+            return;
+        };
+        // the pid can't be reused until the process is waited for
+        let Some(pid) = child.id().filter(|_| matches!(child.try_wait(), Ok(None))) else {
+            return;
+        };
+        // SIGTERM is sent right away, so that it's sent even if search-tui is
+        // exiting
+        signal(&mut child, pid, false);
+        tokio::spawn(async move {
+            if tokio::time::timeout(KILL_DELAY, child.wait())
+                .await
+                .is_err()
+            {
+                signal(&mut child, pid, true);
+                let _ = child.wait().await;
+            }
+        });
+    }
+}
+
+/// sends SIGTERM, or SIGKILL if `kill`, to the process group of a search
+/// command. failures are ignored, the command may have exited in the meantime
+#[cfg(unix)]
+fn signal(_child: &mut Child, pid: u32, kill: bool) {
+    let signal = if kill { libc::SIGKILL } else { libc::SIGTERM };
+    // the process group has the id of its leader, and a negative pid stands
+    // for a process group. the pid isn't reused while the child isn't waited
+    // for
+    unsafe { libc::kill(-(pid as libc::pid_t), signal) };
+}
+
+/// there are no signals, so the command is killed right away
+#[cfg(not(unix))]
+fn signal(child: &mut Child, _pid: u32, _kill: bool) {
+    let _ = child.start_kill();
+}
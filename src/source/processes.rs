@@ -0,0 +1,74 @@
+//! running processes, as listed by `ps`
+
+use std::path::Path;
+
+use anyhow::Context;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use tokio::process::Command;
+
+use crate::{filter::filter, SearchResult, SearchResultEntry};
+
+#[derive(Deserialize, JsonSchema)]
+pub struct ProcessesSource {}
+
+impl ProcessesSource {
+    pub async fn search(&self, query: &str) -> anyhow::Result<SearchResult> {
+        let output = Command::new("ps")
+            .args(["-eo", "pid=,user=,pcpu=,pmem=,args="])
+            .kill_on_drop(true)
+            .output()
+            .await
+            .context("unable to run ps")?;
+        output.status.exit_ok().context("ps failed")?;
+
+        let own_pid = std::process::id().to_string();
+        let entries = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(parse_line)
+            .filter(|entry| entry.identifier != own_pid)
+            .collect::<Vec<_>>();
+        Ok(filter(entries, query))
+    }
+}
+
+fn parse_line(line: &str) -> Option<SearchResultEntry> {
+    let mut rest = line.trim_start();
+    let mut next_field = || {
+        let (field, tail) = rest.split_once(char::is_whitespace)?;
+        rest = tail.trim_start();
+        Some(field)
+    };
+    let pid = next_field()?;
+    let user = next_field()?;
+    let cpu: f64 = next_field()?.parse().ok()?;
+    let mem: f64 = next_field()?.parse().ok()?;
+    let args = rest.trim_end();
+
+    // kernel threads are shown as [name]
+    let program = args.split_whitespace().next().unwrap_or_default();
+    let name = match program
+        .strip_prefix('[')
+        .and_then(|name| name.strip_suffix(']'))
+    {
+        Some(name) => name,
+        None => Path::new(program)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(program),
+    };
+
+    Some(SearchResultEntry {
+        confidence: 1.0,
+        identifier: pid.to_owned(),
+        title: args.to_owned(),
+        extra: Map::from_iter([
+            ("pid".to_owned(), Value::from(pid)),
+            ("user".to_owned(), Value::from(user)),
+            ("name".to_owned(), Value::from(name)),
+            ("cpu".to_owned(), Value::from(cpu)),
+            ("mem".to_owned(), Value::from(mem)),
+        ]),
+    })
+}
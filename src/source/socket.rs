@@ -0,0 +1,69 @@
+//! a long-lived local daemon answering searches over a unix domain socket,
+//! which avoids the startup cost of heavyweight indexes on every query
+
+use anyhow::Context;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+};
+
+use super::json;
+use crate::{template, Limits, SearchResult};
+
+#[derive(Deserialize, JsonSchema)]
+pub struct SocketSource {
+    /// the path of the socket the daemon listens on, e.g.
+    /// `$XDG_RUNTIME_DIR/index.sock`
+    #[serde(deserialize_with = "template::deserialize_expanded")]
+    pub socket: String,
+}
+
+impl SocketSource {
+    /// connects to the daemon and writes the query as a json line. the daemon
+    /// answers with a json [`SearchResult`] (or `{"error": "..."}`) and closes
+    /// the connection
+    pub async fn search(
+        &self,
+        query: &str,
+        path: &str,
+        limits: &Limits,
+    ) -> anyhow::Result<SearchResult> {
+        #[derive(Serialize)]
+        struct Request<'a> {
+            query: &'a str,
+            path: &'a str,
+        }
+
+        let mut stream = UnixStream::connect(&self.socket)
+            .await
+            .with_context(|| format!("unable to connect to {}", self.socket))?;
+        let mut request = serde_json::to_vec(&Request { query, path })?;
+        request.push(b'\n');
+        stream
+            .write_all(&request)
+            .await
+            .context("unable to send the query to the daemon")?;
+        // the daemon may read until the end of the request instead of a line
+        stream.shutdown().await?;
+
+        let mut response = Vec::new();
+        (&mut stream)
+            .take(limits.max_output_bytes + 1)
+            .read_to_end(&mut response)
+            .await
+            .context("unable to read the response of the daemon")?;
+        if let Ok(Value::Object(response)) = serde_json::from_slice::<Value>(&response) {
+            if let Some(error) = response.get("error").and_then(Value::as_str) {
+                anyhow::bail!("daemon error: {error}");
+            }
+        }
+        let truncated = response.len() as u64 > limits.max_output_bytes;
+        if truncated {
+            response.truncate(limits.max_output_bytes as usize);
+        }
+        json::parse(&response, limits.max_results, truncated)
+    }
+}
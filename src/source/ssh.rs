@@ -0,0 +1,270 @@
+//! hosts from the ssh client configuration and `known_hosts`
+
+use std::{
+    collections::HashSet,
+    env, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::{filter::filter, SearchResult, SearchResultEntry};
+
+#[derive(Deserialize, JsonSchema)]
+pub struct SshHostsSource {
+    /// defaults to `~/.ssh/config`
+    config_file: Option<PathBuf>,
+    /// defaults to `~/.ssh/known_hosts`
+    known_hosts_file: Option<PathBuf>,
+}
+
+impl SshHostsSource {
+    pub fn search(&self, query: &str) -> anyhow::Result<SearchResult> {
+        let ssh_dir = env::var_os("HOME").map(|home| PathBuf::from(home).join(".ssh"));
+        let default_path = |name| ssh_dir.as_ref().map(|dir| dir.join(name));
+
+        let mut entries = Vec::new();
+        let mut warnings = Vec::new();
+        if let Some(path) = self.config_file.clone().or_else(|| default_path("config")) {
+            let mut config = ClientConfig {
+                ssh_dir: ssh_dir.as_deref(),
+                including: Vec::new(),
+                entries: &mut entries,
+                warnings: &mut warnings,
+            };
+            config.parse(&path)?;
+        }
+        if let Some(path) = self
+            .known_hosts_file
+            .clone()
+            .or_else(|| default_path("known_hosts"))
+        {
+            parse_known_hosts(&path, &mut entries)?;
+        }
+
+        // hosts from the config take precedence over known_hosts
+        let mut seen = HashSet::new();
+        entries.retain(|entry| seen.insert(entry.identifier.clone()));
+        let mut result = filter(entries, query);
+        result.warnings.extend(warnings);
+        Ok(result)
+    }
+}
+
+fn read_optional(path: &Path) -> anyhow::Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(anyhow::anyhow!("unable to read {}: {err}", path.display())),
+    }
+}
+
+/// an entry with all extra fields present (possibly null), so that templates
+/// can reference them for every host
+fn host_entry(host: &str, origin: &str) -> SearchResultEntry {
+    SearchResultEntry {
+        confidence: 1.0,
+        identifier: host.to_owned(),
+        title: host.to_owned(),
+        extra: Map::from_iter([
+            ("origin".to_owned(), Value::from(origin)),
+            ("hostname".to_owned(), Value::Null),
+            ("user".to_owned(), Value::Null),
+            ("port".to_owned(), Value::Null),
+        ]),
+    }
+}
+
+/// as many nested includes as ssh follows
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// the ssh client configuration being parsed
+struct ClientConfig<'a> {
+    ssh_dir: Option<&'a Path>,
+    /// the canonical paths of the files whose `Include`s are being followed,
+    /// outermost first
+    including: Vec<PathBuf>,
+    entries: &'a mut Vec<SearchResultEntry>,
+    /// the includes that were skipped
+    warnings: &'a mut Vec<String>,
+}
+
+impl ClientConfig<'_> {
+    /// collects the non-wildcard `Host` aliases, along with their `HostName`,
+    /// `User` and `Port` options as extra fields
+    fn parse(&mut self, path: &Path) -> anyhow::Result<()> {
+        let Some(content) = read_optional(path)? else {
+            return Ok(());
+        };
+
+        // the entries of the current `Host` block
+        let mut block_start = self.entries.len();
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (keyword, value) = line
+                .split_once(|c: char| c.is_whitespace() || c == '=')
+                .map(|(keyword, value)| {
+                    (keyword, value.trim_start_matches([' ', '\t', '=']).trim())
+                })
+                .unwrap_or((line, ""));
+            match keyword.to_lowercase().as_str() {
+                "host" => {
+                    block_start = self.entries.len();
+                    self.entries.extend(
+                        value
+                            .split_whitespace()
+                            .filter(|host| !host.contains(['*', '?', '!']))
+                            .map(|host| host_entry(host, "config")),
+                    );
+                }
+                "match" => block_start = self.entries.len(),
+                option @ ("hostname" | "user" | "port") => {
+                    for entry in &mut self.entries[block_start..] {
+                        entry.extra.insert(option.to_owned(), Value::from(value));
+                    }
+                }
+                "include" => {
+                    self.including.push(canonical(path));
+                    for pattern in value.split_whitespace() {
+                        for included in expand_include(pattern, self.ssh_dir) {
+                            self.include(&included)?;
+                        }
+                    }
+                    self.including.pop();
+                    block_start = self.entries.len();
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// parses an included file, unless it is already being parsed or too
+    /// deeply nested
+    fn include(&mut self, path: &Path) -> anyhow::Result<()> {
+        if self.including.contains(&canonical(path)) {
+            self.warnings
+                .push(format!("skipped {}, which includes itself", path.display()));
+            return Ok(());
+        }
+        if self.including.len() >= MAX_INCLUDE_DEPTH {
+            self.warnings.push(format!(
+                "skipped {}, since includes are nested at most {MAX_INCLUDE_DEPTH} deep",
+                path.display()
+            ));
+            return Ok(());
+        }
+        self.parse(path)
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// resolves an `Include` argument, supporting `~` and wildcards in the file
+/// name
+fn expand_include(pattern: &str, ssh_dir: Option<&Path>) -> Vec<PathBuf> {
+    let path = match pattern.strip_prefix("~/") {
+        Some(rest) => match env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(rest),
+            None => return Vec::new(),
+        },
+        // relative paths are relative to ~/.ssh
+        None => match ssh_dir {
+            Some(ssh_dir) => ssh_dir.join(pattern),
+            None => PathBuf::from(pattern),
+        },
+    };
+
+    let Some(file_pattern) = path.file_name().and_then(|name| name.to_str()) else {
+        return Vec::new();
+    };
+    if !file_pattern.contains(['*', '?']) {
+        return vec![path];
+    }
+
+    let Some(dir) = path.parent() else {
+        return Vec::new();
+    };
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|dir_entry| dir_entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| wildcard_match(file_pattern, name))
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let mut pattern_chars = pattern.chars();
+    match pattern_chars.next() {
+        None => text.is_empty(),
+        Some('*') => {
+            let rest = pattern_chars.as_str();
+            text.char_indices()
+                .map(|(index, _)| index)
+                .chain([text.len()])
+                .any(|index| wildcard_match(rest, &text[index..]))
+        }
+        Some(p) => {
+            let mut text_chars = text.chars();
+            text_chars.next().is_some_and(|t| p == '?' || p == t)
+                && wildcard_match(pattern_chars.as_str(), text_chars.as_str())
+        }
+    }
+}
+
+/// collects the hosts of `known_hosts`, skipping hashed entries
+fn parse_known_hosts(path: &Path, entries: &mut Vec<SearchResultEntry>) -> anyhow::Result<()> {
+    let Some(content) = read_optional(path)? else {
+        return Ok(());
+    };
+
+    for line in content.lines() {
+        let mut fields = line.split_whitespace();
+        let mut hosts = fields.next();
+        if hosts.is_some_and(|hosts| hosts.starts_with('@')) {
+            // marker, e.g. @cert-authority
+            hosts = fields.next();
+        }
+        let Some(hosts) = hosts.filter(|hosts| !hosts.starts_with('#')) else {
+            continue;
+        };
+
+        for host in hosts.split(',') {
+            if host.starts_with('|') || host.contains(['*', '?', '!']) {
+                continue;
+            }
+
+            // non-standard ports are written as [host]:port
+            match host
+                .strip_prefix('[')
+                .and_then(|host| host.split_once("]:"))
+            {
+                Some((host, port)) => {
+                    let mut entry = host_entry(host, "known_hosts");
+                    entry.extra.insert("port".to_owned(), Value::from(port));
+                    entries.push(entry);
+                }
+                None => entries.push(host_entry(host, "known_hosts")),
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,201 @@
+//! items read from stdin after the config, like dmenu or fzf
+
+use std::io::BufRead;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::Map;
+
+use crate::{filter::filter_by, SearchResult, SearchResultEntry};
+
+#[derive(Deserialize, JsonSchema)]
+pub struct StdinSource {
+    /// splits items into fields, whitespace (ignoring leading and trailing
+    /// whitespace) by default
+    pub delimiter: Option<String>,
+    /// the fields matched against the query, e.g. `2` or `1,3..`
+    pub nth: Option<String>,
+    /// the fields displayed as the title
+    pub with_nth: Option<String>,
+    #[serde(skip)]
+    items: Vec<Item>,
+}
+
+struct Item {
+    /// the whole item, which is the identifier of its entry
+    text: String,
+    title: String,
+    /// the part of the item matched against the query
+    match_text: String,
+}
+
+/// a field or a range of fields, 1-based, with negative numbers counting from
+/// the last field
+#[derive(Clone, Copy)]
+struct FieldRange {
+    start: Option<i64>,
+    end: Option<i64>,
+}
+
+impl StdinSource {
+    /// fails if `nth` or `with_nth` aren't valid field ranges
+    pub fn check(&self) -> anyhow::Result<()> {
+        for ranges in [&self.nth, &self.with_nth].into_iter().flatten() {
+            parse_field_ranges(ranges)?;
+        }
+        Ok(())
+    }
+
+    /// reads the items, one per line, or NUL-terminated if `read0` is set.
+    /// `after_config` is whether `input` starts right after a config line
+    pub fn read(
+        &mut self,
+        input: &mut impl BufRead,
+        read0: bool,
+        after_config: bool,
+    ) -> anyhow::Result<()> {
+        let nth = self.nth.as_deref().map(parse_field_ranges).transpose()?;
+        let with_nth = self
+            .with_nth
+            .as_deref()
+            .map(parse_field_ranges)
+            .transpose()?;
+        let delimiter = if read0 { b'\0' } else { b'\n' };
+        for (index, item) in input.split(delimiter).enumerate() {
+            let item = String::from_utf8_lossy(&item?).into_owned();
+            let item = if read0 {
+                // the newline ending the config is only split off by itself
+                // when items are newline-terminated
+                match item.strip_prefix("\r\n").or(item.strip_prefix('\n')) {
+                    Some(rest) if index == 0 && after_config => rest.to_owned(),
+                    _ => item,
+                }
+            } else {
+                item.strip_suffix('\r').map(str::to_string).unwrap_or(item)
+            };
+            // the newline ending the config isn't an empty item
+            if item.is_empty() {
+                continue;
+            }
+
+            let fields = |ranges: &Option<Vec<FieldRange>>| match ranges {
+                Some(ranges) => self.select_fields(&item, ranges),
+                None => item.clone(),
+            };
+            self.items.push(Item {
+                title: fields(&with_nth),
+                match_text: fields(&nth),
+                text: item,
+            });
+        }
+        Ok(())
+    }
+
+    /// the fields of `item` in `ranges`, joined with the delimiter
+    fn select_fields(&self, item: &str, ranges: &[FieldRange]) -> String {
+        let fields: Vec<&str> = match &self.delimiter {
+            Some(delimiter) => item.split(delimiter.as_str()).collect(),
+            None => item.split_whitespace().collect(),
+        };
+        let selected: Vec<&str> = ranges
+            .iter()
+            .flat_map(|range| range.indices(fields.len()))
+            .map(|index| fields[index])
+            .collect();
+        selected.join(self.delimiter.as_deref().unwrap_or(" "))
+    }
+
+    pub fn search(&self, query: &str) -> anyhow::Result<SearchResult> {
+        let entries = self.items.iter().map(|item| {
+            let entry = SearchResultEntry {
+                confidence: 1.0,
+                identifier: item.text.clone(),
+                title: item.title.clone(),
+                extra: Map::new(),
+            };
+            (entry, item.match_text.clone())
+        });
+        Ok(filter_by(entries, query))
+    }
+}
+
+impl FieldRange {
+    fn parse(range: &str) -> anyhow::Result<Self> {
+        let invalid = || anyhow::anyhow!("invalid field range {range:?}");
+        let bound = |bound: &str| -> anyhow::Result<Option<i64>> {
+            match bound {
+                "" => Ok(None),
+                bound => match bound.parse() {
+                    Ok(0) | Err(_) => Err(invalid()),
+                    Ok(bound) => Ok(Some(bound)),
+                },
+            }
+        };
+        match range.split_once("..") {
+            Some((start, end)) => Ok(Self {
+                start: bound(start)?,
+                end: bound(end)?,
+            }),
+            None => {
+                let field = bound(range)?.ok_or_else(invalid)?;
+                Ok(Self {
+                    start: Some(field),
+                    end: Some(field),
+                })
+            }
+        }
+    }
+
+    /// the 0-based indices of the fields in the range, out of `len` fields
+    fn indices(self, len: usize) -> std::ops::Range<usize> {
+        let len = len as i64;
+        let index = |field: i64| if field < 0 { len + field } else { field - 1 };
+        let start = self.start.map_or(0, index).clamp(0, len);
+        let end = self
+            .end
+            .map_or(len, |field| index(field) + 1)
+            .clamp(start, len);
+        start as usize..end as usize
+    }
+}
+
+/// parses comma-separated field ranges like `1,3..5,-1`
+fn parse_field_ranges(ranges: &str) -> anyhow::Result<Vec<FieldRange>> {
+    ranges
+        .split(',')
+        .map(|range| FieldRange::parse(range.trim()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StdinSource;
+
+    fn items(input: &[u8], read0: bool, after_config: bool) -> Vec<String> {
+        let mut source = StdinSource {
+            delimiter: None,
+            nth: None,
+            with_nth: None,
+            items: Vec::new(),
+        };
+        source.read(&mut &input[..], read0, after_config).unwrap();
+        source.items.into_iter().map(|item| item.text).collect()
+    }
+
+    #[test]
+    fn newline_ending_the_config_is_not_an_item() {
+        assert_eq!(items(b"\nfoo\nbar\n", false, true), ["foo", "bar"]);
+        assert_eq!(items(b"\r\nfoo\r\nbar", false, true), ["foo", "bar"]);
+    }
+
+    #[test]
+    fn newline_ending_the_config_is_stripped_with_read0() {
+        assert_eq!(items(b"\nfoo\0bar\nbaz\0", true, true), ["foo", "bar\nbaz"]);
+        assert_eq!(items(b"\r\nfoo\0bar\0", true, true), ["foo", "bar"]);
+    }
+
+    #[test]
+    fn leading_newline_is_kept_without_a_config() {
+        assert_eq!(items(b"\nfoo\0bar\0", true, false), ["\nfoo", "bar"]);
+    }
+}
@@ -0,0 +1,87 @@
+//! search suggestions from a web search engine's autocomplete endpoint
+
+use std::sync::OnceLock;
+
+use anyhow::Context;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::http::read_body;
+use crate::{
+    template::{self, Template},
+    Limits, SearchResult, SearchResultEntry,
+};
+
+#[derive(Deserialize, JsonSchema)]
+pub struct WebSuggestionsSource {
+    /// a template of the suggestion url, with the `{query}` template variable,
+    /// e.g. `https://duckduckgo.com/ac/?q={query | url}&type=list`
+    #[serde(deserialize_with = "template::deserialize_expanded")]
+    pub url: String,
+}
+
+/// shared between searches to reuse connections
+pub fn client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(reqwest::Client::new)
+}
+
+impl WebSuggestionsSource {
+    pub async fn search(&self, query: &str, limits: &Limits) -> anyhow::Result<SearchResult> {
+        #[derive(Serialize)]
+        struct Context<'a> {
+            query: &'a str,
+        }
+
+        if query.is_empty() {
+            return Ok(SearchResult::new(Vec::new()));
+        }
+
+        let url = Template::new(&self.url)?.render(&Context { query })?;
+        let mut response = client()
+            .get(&url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("unable to fetch suggestions from {url}"))?;
+        let (body, truncated) = read_body(&mut response, limits).await?;
+        if truncated {
+            anyhow::bail!(
+                "the suggestions from {url} are larger than {} bytes",
+                limits.max_output_bytes
+            );
+        }
+        // some engines don't respond in utf-8
+        let body = String::from_utf8_lossy(&body).into_owned();
+
+        // the opensearch suggestions format: [query, [suggestion, ...], ...]
+        let suggestions = match serde_json::from_str(&body)? {
+            Value::Array(values) => values.into_iter().nth(1),
+            _ => None,
+        }
+        .and_then(|suggestions| match suggestions {
+            Value::Array(suggestions) => Some(suggestions),
+            _ => None,
+        })
+        .context("unexpected suggestions response")?;
+
+        let num_suggestions = suggestions.len();
+        let results = suggestions
+            .into_iter()
+            .filter_map(|suggestion| match suggestion {
+                Value::String(suggestion) => Some(suggestion),
+                _ => None,
+            })
+            .enumerate()
+            .map(|(index, suggestion)| SearchResultEntry {
+                // the suggestions are already ranked
+                confidence: 1.0 - index as f64 / num_suggestions as f64,
+                identifier: suggestion.clone(),
+                title: suggestion,
+                extra: Map::new(),
+            })
+            .collect();
+        Ok(SearchResult::new(results))
+    }
+}
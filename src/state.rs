@@ -0,0 +1,1886 @@
+//! the picker as a state machine: the event loop translates terminal events
+//! into [`Input`]s, feeds them to [`State::update`] and carries out the
+//! returned [`Effect`]s, so that the picker logic doesn't depend on the
+//! terminal or on running searches
+
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    ops::Range,
+    time::{Duration, Instant},
+};
+
+use serde_json::Map;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    action::CopyField,
+    filter::{filter, match_score, matches},
+    Config, SearchResult, SearchResultEntry, SortOrder,
+};
+
+/// how many killed texts are kept for yanking
+const KILL_RING_SIZE: usize = 16;
+/// how many query edits can be undone
+const UNDO_LIMIT: usize = 100;
+
+pub struct State {
+    query: String,
+    /// the byte index of the edit cursor in the query
+    query_cursor: usize,
+    /// whether the whole query is selected, so that typing replaces it
+    query_selected: bool,
+    /// the directories entered in drill-down mode, the last one being current
+    path_stack: Vec<String>,
+    result: Option<SearchResult>,
+    sort_order: SortOrder,
+    /// the weight of the fuzzy match score in the confidence of the results,
+    /// if they are reranked
+    fuzzy_rerank: Option<f64>,
+    /// whether entries with the same identifier are collapsed
+    dedupe: bool,
+    /// the identifiers of the pinned entries, `None` unless pinning is
+    /// enabled
+    pinned: Option<Vec<String>>,
+    /// the frecency of past accepted entries by identifier, `None` unless
+    /// the `frecency` sort order is enabled
+    frecencies: Option<HashMap<String, f64>>,
+    order: Order,
+    /// the sources of the results, in backend order
+    sources: Vec<SourceCount>,
+    /// the sources whose results are hidden
+    collapsed: Vec<String>,
+    selected_index: usize,
+    /// whether a search has been started and hasn't finished yet
+    searching: bool,
+    /// whether the search in flight was started by a refresh, in which case
+    /// the selected entry is kept
+    refreshing: bool,
+    /// whether entries of the search in flight have been shown, which are
+    /// replaced when it finishes
+    streamed: bool,
+    /// queries shorter than this (in characters) aren't searched for
+    min_query_length: usize,
+    /// whether the results of the last search are filtered locally while
+    /// the next one runs
+    local_filter: bool,
+    /// the results of the last search, while the locally filtered ones are
+    /// shown
+    unfiltered: Option<SearchResult>,
+    drill_down: bool,
+    /// whether a preview command is configured
+    has_preview: bool,
+    preview_visible: bool,
+    /// the identifier of the entry whose preview was last requested
+    previewed: Option<String>,
+    /// the identifier and content of the last finished preview
+    preview: Option<(String, String)>,
+    /// how long finished previews are cached for, if they are
+    preview_ttl: Option<Duration>,
+    /// the finished previews and when they finished, by identifier
+    preview_cache: HashMap<String, (Instant, String)>,
+    /// the outcome of the last background command, or another notification
+    message: Option<String>,
+    /// the error of the last search, kept until a search succeeds
+    search_error: Option<String>,
+    multi_select: bool,
+    /// the maximum number of results for which the create entry is shown, if
+    /// it is enabled
+    create_threshold: Option<usize>,
+    /// the marked entries, in the order they were marked
+    marks: Vec<SearchResultEntry>,
+    /// the past queries, most frecent first
+    query_history: Vec<String>,
+    /// the past query recalled with ctrl+p, if any, and the query typed
+    /// before recalling it
+    recall: Option<(usize, String)>,
+    /// the query history picker, if it is open
+    history: Option<Box<HistoryPicker>>,
+    /// the texts killed with ctrl+u, ctrl+k and ctrl+w, newest last
+    kill_ring: Vec<String>,
+    /// the start of the text inserted by the last yank and how many kills
+    /// back it is, so that alt+y can replace it with an older one
+    yank: Option<(usize, usize)>,
+    /// the queries and cursors before the edits that can be undone, newest
+    /// last
+    undo: Vec<(String, usize)>,
+    /// the queries and cursors before the undone edits, newest last
+    redo: Vec<(String, usize)>,
+    /// the cursor after the last typed word char, so that typing a word is
+    /// undone at once
+    typing: Option<usize>,
+}
+
+/// the query history picker, which temporarily replaces the query and the
+/// results with the past queries
+struct HistoryPicker {
+    queries: Vec<SearchResultEntry>,
+    /// the picker the history was opened from, swapped back in when the
+    /// history is closed
+    saved: Picker,
+}
+
+#[derive(Default)]
+struct Picker {
+    query: String,
+    query_cursor: usize,
+    query_selected: bool,
+    result: Option<SearchResult>,
+    order: Order,
+    sources: Vec<SourceCount>,
+    selected_index: usize,
+}
+
+/// the display order of the results, which streamed results are merged into
+/// rather than sorting all of them again
+#[derive(Default)]
+struct Order {
+    /// the backend indices of the results in display order, `None` if they
+    /// are displayed in backend order
+    indices: Option<Vec<usize>>,
+    /// the display position of each result, by backend index, `None` for
+    /// those from collapsed sources
+    positions: Vec<Option<usize>>,
+    /// the backend index of the first result with each identifier
+    by_identifier: HashMap<String, usize>,
+}
+
+/// the number of results from a source, see [`SearchResultEntry::source`]
+pub struct SourceCount {
+    pub name: String,
+    pub count: usize,
+    pub collapsed: bool,
+}
+
+/// a row of the result list
+pub enum Row<'a> {
+    /// a result, with its position in the sorted results
+    Entry(usize, &'a SearchResultEntry),
+    /// the query suggested by the backend, which replaces the query when
+    /// accepted
+    Suggestion(&'a str),
+    /// a source whose results are hidden, which expands it when accepted
+    Collapsed(&'a SourceCount),
+    /// the entry for creating the query, shown when there are few results
+    Create,
+}
+
+pub enum Input {
+    /// replaces the query with a selected one, e.g. from the last run
+    RestoreQuery(String),
+    /// inserts a char at the cursor
+    Insert(char),
+    /// deletes the grapheme before the cursor
+    DeleteBackward,
+    /// deletes the grapheme after the cursor
+    DeleteForward,
+    /// kills the word before the cursor
+    DeleteWordBackward,
+    /// kills the query before the cursor
+    KillToStart,
+    /// kills the query after the cursor
+    KillToEnd,
+    /// inserts the last killed text at the cursor
+    Yank,
+    /// replaces the text inserted by the last yank with the kill before it
+    YankPop,
+    /// reverts the last query edit
+    Undo,
+    /// applies the last undone query edit again
+    Redo,
+    MoveCursorLeft,
+    MoveCursorRight,
+    MoveWordLeft,
+    MoveWordRight,
+    MoveCursorStart,
+    MoveCursorEnd,
+    SelectPrevious,
+    SelectNext,
+    /// moves the selection up by this many rows, stopping at the first one
+    SelectPreviousPage(usize),
+    /// moves the selection down by this many rows, stopping at the last one
+    SelectNextPage(usize),
+    /// selects the row at this index, e.g. one that was clicked
+    Select(usize),
+    Accept,
+    /// switches to the next sort order
+    ToggleSort,
+    /// shows or hides the preview pane
+    TogglePreview,
+    /// runs the preview command again for the selected entry, replacing its
+    /// cached preview
+    RefreshPreview,
+    /// opens or closes the query history picker
+    ToggleHistory,
+    /// replaces the query with the previous (older) past query
+    RecallPrevious,
+    /// replaces the query with the next (newer) past query, or the typed one
+    RecallNext,
+    /// marks or unmarks the selected entry and selects the next one
+    ToggleMark,
+    /// pins or unpins the selected entry
+    TogglePin,
+    /// hides the results from the source of the selected entry, or shows
+    /// those of the selected collapsed source
+    ToggleCollapse,
+    /// copies a part of the selected entry, keeping the picker open
+    Copy(CopyField),
+    /// runs the `execute_silent` action with this index for the selected entry
+    ExecuteSilent(usize),
+    ExecuteSilentFinished(String),
+    Cancel,
+    /// re-runs the current search unless one is already in flight
+    Refresh,
+    /// entries of the search in flight, sent by streaming sources before it
+    /// finishes
+    SearchProgress(Vec<SearchResultEntry>),
+    SearchFinished(anyhow::Result<SearchResult>),
+    PreviewFinished {
+        identifier: String,
+        content: String,
+    },
+}
+
+impl Input {
+    /// whether the input edits the query at the cursor, which can be undone
+    fn edits_query(&self) -> bool {
+        matches!(
+            self,
+            Input::Insert(_)
+                | Input::DeleteBackward
+                | Input::DeleteForward
+                | Input::DeleteWordBackward
+                | Input::KillToStart
+                | Input::KillToEnd
+                | Input::Yank
+                | Input::YankPop
+        )
+    }
+}
+
+pub enum Effect {
+    /// starts a search, superseding the one in flight
+    Search {
+        query: String,
+        path: String,
+        /// whether the results must be fetched again instead of taken from
+        /// the cache
+        refresh: bool,
+    },
+    /// stops the search in flight without starting another one, since the
+    /// query is too short. the result list has been cleared
+    SearchSkipped,
+    QueryChanged,
+    /// the result list has been replaced
+    ResultsChanged,
+    SelectionChanged,
+    Error(String),
+    /// copies the text to the clipboard
+    Copy(String),
+    /// starts previewing the entry, superseding the preview in flight
+    Preview(SearchResultEntry),
+    /// the preview has been shown, hidden or updated
+    PreviewChanged,
+    /// runs an `execute_silent` action in the background
+    ExecuteSilent {
+        index: usize,
+        entry: SearchResultEntry,
+    },
+    StatusChanged,
+    /// an entry has been pinned or unpinned, these are the pinned
+    /// identifiers to save
+    PinnedChanged(Vec<String>),
+    /// exits the picker with the accepted entries, none if it was cancelled
+    Exit(Vec<SearchResultEntry>),
+}
+
+impl State {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            query: String::new(),
+            query_cursor: 0,
+            query_selected: false,
+            path_stack: Vec::new(),
+            result: None,
+            sort_order: config.sort,
+            fuzzy_rerank: config.fuzzy_rerank.map(|weight| weight.clamp(0.0, 1.0)),
+            dedupe: config.dedupe,
+            pinned: None,
+            frecencies: None,
+            order: Order::default(),
+            sources: Vec::new(),
+            collapsed: Vec::new(),
+            selected_index: 0,
+            searching: false,
+            refreshing: false,
+            streamed: false,
+            min_query_length: config.min_query_length,
+            local_filter: config.local_filter,
+            unfiltered: None,
+            drill_down: config.drill_down,
+            has_preview: config.preview.is_some(),
+            preview_visible: config
+                .preview
+                .as_ref()
+                .is_some_and(|preview| preview.visible),
+            previewed: None,
+            preview: None,
+            preview_ttl: config
+                .preview
+                .as_ref()
+                .and_then(|preview| preview.cache_millis)
+                .map(Duration::from_millis),
+            preview_cache: HashMap::new(),
+            message: None,
+            search_error: None,
+            multi_select: config.multi_select,
+            create_threshold: config.create_entry.as_ref().map(|create| create.threshold),
+            marks: Vec::new(),
+            query_history: Vec::new(),
+            recall: None,
+            history: None,
+            kill_ring: Vec::new(),
+            yank: None,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            typing: None,
+        }
+    }
+
+    /// sets the past queries shown by the history picker, most frecent first
+    pub fn set_query_history(&mut self, queries: Vec<String>) {
+        self.query_history = queries;
+    }
+
+    /// enables pinning, with the identifiers of the pinned entries
+    pub fn set_pinned(&mut self, pinned: Vec<String>) {
+        self.pinned = Some(pinned);
+    }
+
+    pub fn is_pinned(&self, entry: &SearchResultEntry) -> bool {
+        self.pinned
+            .as_ref()
+            .is_some_and(|pinned| pinned.contains(&entry.identifier))
+    }
+
+    /// enables the `frecency` sort order, with the frecency of past accepted
+    /// entries
+    pub fn set_frecencies(&mut self, frecencies: HashMap<String, f64>) {
+        self.frecencies = Some(frecencies);
+    }
+
+    pub fn in_history(&self) -> bool {
+        self.history.is_some()
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn query_selected(&self) -> bool {
+        self.query_selected
+    }
+
+    /// the byte index of the edit cursor in the query
+    pub fn query_cursor(&self) -> usize {
+        self.query_cursor
+    }
+
+    /// replaces the query, moving the cursor to its end
+    fn set_query(&mut self, query: String) {
+        self.typing = None;
+        self.query_cursor = query.len();
+        self.query = query;
+    }
+
+    fn insert_at_cursor(&mut self, c: char) {
+        self.query.insert(self.query_cursor, c);
+        self.query_cursor += c.len_utf8();
+    }
+
+    /// the start of the grapheme (user-perceived character, e.g. an emoji
+    /// made of several chars) before the cursor
+    fn previous_grapheme_start(&self) -> Option<usize> {
+        self.query[..self.query_cursor]
+            .grapheme_indices(true)
+            .next_back()
+            .map(|(index, _)| index)
+    }
+
+    /// the end of the grapheme after the cursor
+    fn next_grapheme_end(&self) -> Option<usize> {
+        self.query[self.query_cursor..]
+            .graphemes(true)
+            .next()
+            .map(|grapheme| self.query_cursor + grapheme.len())
+    }
+
+    /// deletes the grapheme before the cursor, returning whether there was
+    /// one
+    fn delete_backward(&mut self) -> bool {
+        let Some(start) = self.previous_grapheme_start() else {
+            return false;
+        };
+        self.query.drain(start..self.query_cursor);
+        self.query_cursor = start;
+        true
+    }
+
+    /// deletes the grapheme after the cursor, returning whether there was one
+    fn delete_forward(&mut self) -> bool {
+        let Some(end) = self.next_grapheme_end() else {
+            return false;
+        };
+        self.query.drain(self.query_cursor..end);
+        true
+    }
+
+    /// removes `range` from the query and adds it to the kill ring,
+    /// returning whether it was empty
+    fn kill(&mut self, range: Range<usize>) -> bool {
+        if range.is_empty() {
+            return false;
+        }
+        self.query_cursor = range.start;
+        let killed = self.query.drain(range).collect();
+        if self.kill_ring.len() == KILL_RING_SIZE {
+            self.kill_ring.remove(0);
+        }
+        self.kill_ring.push(killed);
+        true
+    }
+
+    /// kills the input's part of the query, returning whether it changed
+    fn kill_for(&mut self, input: &Input) -> bool {
+        let range = match input {
+            Input::DeleteWordBackward => self.previous_word_start()..self.query_cursor,
+            Input::KillToStart => 0..self.query_cursor,
+            Input::KillToEnd => self.query_cursor..self.query.len(),
+            _ => return false,
+        };
+        self.kill(range)
+    }
+
+    /// inserts the last killed text at the cursor, returning whether there
+    /// was one
+    fn yank(&mut self) -> bool {
+        let Some(text) = self.kill_ring.last() else {
+            return false;
+        };
+        self.query.insert_str(self.query_cursor, text);
+        self.yank = Some((self.query_cursor, 0));
+        self.query_cursor += text.len();
+        true
+    }
+
+    /// replaces the text inserted by the last yank with the kill before it,
+    /// returning whether the last input was a yank
+    fn yank_pop(&mut self) -> bool {
+        let Some((start, age)) = self.yank else {
+            return false;
+        };
+        let age = (age + 1) % self.kill_ring.len();
+        let text = &self.kill_ring[self.kill_ring.len() - 1 - age];
+        self.query.replace_range(start..self.query_cursor, text);
+        self.query_cursor = start + text.len();
+        self.yank = Some((start, age));
+        true
+    }
+
+    /// the start of the word before the cursor, words being runs of
+    /// alphanumeric chars like in readline
+    fn previous_word_start(&self) -> usize {
+        self.query[..self.query_cursor]
+            .trim_end_matches(|c: char| !c.is_alphanumeric())
+            .trim_end_matches(char::is_alphanumeric)
+            .len()
+    }
+
+    /// the end of the word after the cursor
+    fn next_word_end(&self) -> usize {
+        let rest = self.query[self.query_cursor..]
+            .trim_start_matches(|c: char| !c.is_alphanumeric())
+            .trim_start_matches(char::is_alphanumeric);
+        self.query.len() - rest.len()
+    }
+
+    /// moves the cursor to `cursor`, returning whether it moved
+    fn move_cursor(&mut self, cursor: Option<usize>) -> Vec<Effect> {
+        match cursor {
+            Some(cursor) if cursor != self.query_cursor || self.query_selected => {
+                self.query_cursor = cursor;
+                self.query_selected = false;
+                vec![Effect::QueryChanged]
+            }
+            _ => vec![],
+        }
+    }
+
+    /// the current drill-down directory, empty at the top level
+    pub fn path(&self) -> &str {
+        self.path_stack.last().map_or("", String::as_str)
+    }
+
+    pub fn result(&self) -> Option<&SearchResult> {
+        self.result.as_ref()
+    }
+
+    pub fn sort_order(&self) -> SortOrder {
+        self.sort_order
+    }
+
+    pub fn selected_index(&self) -> usize {
+        self.selected_index
+    }
+
+    /// the row at position `index` of the result list, which starts with the
+    /// suggested query and ends with the collapsed sources and the create
+    /// entry, if they are shown
+    pub fn row(&self, index: usize) -> Option<Row<'_>> {
+        let suggestion = self.suggested_query();
+        let Some(index) = index.checked_sub(suggestion.is_some().into()) else {
+            return suggestion.map(Row::Suggestion);
+        };
+        if let Some(entry) = self.entry(index) {
+            return Some(Row::Entry(index, entry));
+        }
+        let index = index - self.num_results();
+        match self.collapsed_sources().nth(index) {
+            Some(source) => Some(Row::Collapsed(source)),
+            None if index == self.collapsed_sources().count() && self.shows_create() => {
+                Some(Row::Create)
+            }
+            None => None,
+        }
+    }
+
+    pub fn num_rows(&self) -> usize {
+        usize::from(self.suggested_query().is_some())
+            + self.num_results()
+            + self.collapsed_sources().count()
+            + usize::from(self.shows_create())
+    }
+
+    /// the sources of the results, if they come from more than one
+    pub fn sources(&self) -> &[SourceCount] {
+        match self.sources.len() {
+            0 | 1 => &[],
+            _ => &self.sources,
+        }
+    }
+
+    fn collapsed_sources(&self) -> impl Iterator<Item = &SourceCount> {
+        self.sources.iter().filter(|source| source.collapsed)
+    }
+
+    fn is_collapsed(&self, entry: &SearchResultEntry) -> bool {
+        entry
+            .source()
+            .is_some_and(|source| self.collapsed.iter().any(|collapsed| collapsed == source))
+    }
+
+    fn shows_create(&self) -> bool {
+        self.create_threshold.is_some_and(|threshold| {
+            self.result.is_some()
+                && self.history.is_none()
+                && !self.query.is_empty()
+                && self.num_results() <= threshold
+        })
+    }
+
+    fn suggested_query(&self) -> Option<&str> {
+        self.result.as_ref()?.suggested_query.as_deref()
+    }
+
+    /// the row selected when the results are replaced, which is the first
+    /// entry rather than a suggestion
+    fn first_row(&self) -> usize {
+        match self.suggested_query() {
+            Some(_) if self.num_rows() > 1 => 1,
+            _ => 0,
+        }
+    }
+
+    /// whether a search has been started and hasn't finished yet
+    pub fn searching(&self) -> bool {
+        self.searching
+    }
+
+    /// whether the results are those of a previous query, i.e. a search has
+    /// been started but hasn't sent any entries yet
+    pub fn results_stale(&self) -> bool {
+        self.searching && !self.streamed && !self.refreshing && self.history.is_none()
+    }
+
+    /// whether an entry other than the first one is selected, which stays
+    /// selected when new results arrive. otherwise the best new result is
+    /// selected
+    fn navigated(&self) -> bool {
+        self.selected_index != self.first_row()
+    }
+
+    /// the entry at position `index` of the sorted results
+    fn entry(&self, index: usize) -> Option<&SearchResultEntry> {
+        let results = &self.result.as_ref()?.results;
+        match &self.order.indices {
+            Some(order) => results.get(*order.get(index)?),
+            None => results.get(index),
+        }
+    }
+
+    fn selected_entry(&self) -> Option<&SearchResultEntry> {
+        match self.row(self.selected_index)? {
+            Row::Entry(_, entry) => Some(entry),
+            Row::Suggestion(_) | Row::Collapsed(_) | Row::Create => None,
+        }
+    }
+
+    /// the row of the entry with the given identifier
+    fn position(&self, identifier: &str) -> Option<usize> {
+        let index = *self.order.by_identifier.get(identifier)?;
+        let position = (*self.order.positions.get(index)?)?;
+        Some(position + usize::from(self.suggested_query().is_some()))
+    }
+
+    /// the position of `entry` in the shown results, starting from 1
+    pub fn rank(&self, entry: &SearchResultEntry) -> Option<usize> {
+        let index = *self.order.by_identifier.get(&entry.identifier)?;
+        Some((*self.order.positions.get(index)?)? + 1)
+    }
+
+    pub fn is_marked(&self, entry: &SearchResultEntry) -> bool {
+        self.marks
+            .iter()
+            .any(|marked| marked.identifier == entry.identifier)
+    }
+
+    pub fn num_marked(&self) -> usize {
+        self.marks.len()
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    pub fn search_error(&self) -> Option<&str> {
+        self.search_error.as_deref()
+    }
+
+    /// the preview of the selected entry, if it is shown and has finished
+    pub fn preview(&self) -> Option<&str> {
+        let (identifier, content) = self.preview.as_ref().filter(|_| self.preview_visible)?;
+        (self.selected_entry()?.identifier == *identifier).then_some(content.as_str())
+    }
+
+    /// the cached preview of the entry with this identifier, if it hasn't
+    /// expired
+    fn cached_preview(&self, identifier: &str) -> Option<String> {
+        let ttl = self.preview_ttl?;
+        let (finished, content) = self.preview_cache.get(identifier)?;
+        (finished.elapsed() < ttl).then(|| content.clone())
+    }
+
+    /// the number of results shown
+    fn num_results(&self) -> usize {
+        match (&self.result, &self.order.indices) {
+            (None, _) => 0,
+            (Some(_), Some(order)) => order.len(),
+            (Some(result), None) => result.results.len(),
+        }
+    }
+
+    pub fn update(&mut self, input: Input) -> Vec<Effect> {
+        let before = (self.history.is_none() && input.edits_query())
+            .then(|| (self.query.clone(), self.query_cursor));
+        let typing = matches!(input, Input::Insert(c) if !c.is_whitespace());
+        let continues_word = typing && self.typing == Some(self.query_cursor);
+        let mut effects = self.handle(input);
+        if before.is_some() {
+            self.typing = typing.then_some(self.query_cursor);
+        }
+        if let Some(before) = before.filter(|(query, _)| *query != self.query) {
+            if !continues_word {
+                if self.undo.len() == UNDO_LIMIT {
+                    self.undo.remove(0);
+                }
+                self.undo.push(before);
+            }
+            self.redo.clear();
+        }
+        // the preview follows the selected entry
+        let selected = self
+            .selected_entry()
+            .filter(|_| self.preview_visible && self.history.is_none());
+        if selected.map(|entry| &entry.identifier) != self.previewed.as_ref() {
+            let selected = selected.cloned();
+            self.previewed = selected.as_ref().map(|entry| entry.identifier.clone());
+            if let Some(entry) = selected {
+                match self.cached_preview(&entry.identifier) {
+                    Some(content) => {
+                        self.preview = Some((entry.identifier, content));
+                        effects.push(Effect::PreviewChanged);
+                    }
+                    None => effects.push(Effect::Preview(entry)),
+                }
+            }
+        }
+        effects
+    }
+
+    fn handle(&mut self, input: Input) -> Vec<Effect> {
+        // only a yank right before can be replaced by an older kill
+        let yank = self.yank.take();
+        if matches!(input, Input::YankPop) {
+            self.yank = yank;
+        }
+        if self.history.is_some() {
+            return self.handle_in_history(input);
+        }
+        if input.edits_query() || matches!(input, Input::Undo | Input::Redo) {
+            self.recall = None;
+        }
+
+        match input {
+            Input::RestoreQuery(query) => {
+                self.set_query(query);
+                self.query_selected = !self.query.is_empty();
+                vec![Effect::QueryChanged, self.search()]
+            }
+
+            Input::Insert(c) => {
+                if std::mem::take(&mut self.query_selected) {
+                    self.set_query(String::new());
+                }
+                self.insert_at_cursor(c);
+                vec![Effect::QueryChanged, self.search()]
+            }
+
+            Input::DeleteBackward if self.query.is_empty() && !self.path_stack.is_empty() => {
+                self.path_stack.pop();
+                vec![self.search()]
+            }
+
+            Input::DeleteBackward
+            | Input::DeleteForward
+            | Input::DeleteWordBackward
+            | Input::KillToStart
+            | Input::KillToEnd
+                if std::mem::take(&mut self.query_selected) =>
+            {
+                self.set_query(String::new());
+                vec![Effect::QueryChanged, self.search()]
+            }
+
+            Input::DeleteBackward if !self.delete_backward() => vec![],
+
+            Input::DeleteBackward => vec![Effect::QueryChanged, self.search()],
+
+            Input::DeleteWordBackward | Input::KillToStart | Input::KillToEnd
+                if !self.kill_for(&input) =>
+            {
+                vec![]
+            }
+
+            Input::DeleteWordBackward | Input::KillToStart | Input::KillToEnd => {
+                vec![Effect::QueryChanged, self.search()]
+            }
+
+            Input::Yank => {
+                if self.kill_ring.is_empty() {
+                    return vec![];
+                }
+                // like typing, yanking replaces a selected query
+                if std::mem::take(&mut self.query_selected) {
+                    self.set_query(String::new());
+                }
+                self.yank();
+                vec![Effect::QueryChanged, self.search()]
+            }
+
+            Input::YankPop if !self.yank_pop() => vec![],
+
+            Input::YankPop => vec![Effect::QueryChanged, self.search()],
+
+            Input::DeleteForward if !self.delete_forward() => vec![],
+
+            Input::DeleteForward => vec![Effect::QueryChanged, self.search()],
+
+            Input::MoveCursorLeft => self.move_cursor(self.previous_grapheme_start()),
+
+            Input::MoveCursorRight => self.move_cursor(self.next_grapheme_end()),
+
+            Input::Undo | Input::Redo => {
+                let (from, to) = match input {
+                    Input::Undo => (&mut self.undo, &mut self.redo),
+                    _ => (&mut self.redo, &mut self.undo),
+                };
+                let Some((query, cursor)) = from.pop() else {
+                    return vec![];
+                };
+                to.push((std::mem::replace(&mut self.query, query), self.query_cursor));
+                self.query_cursor = cursor;
+                self.query_selected = false;
+                self.typing = None;
+                vec![Effect::QueryChanged, self.search()]
+            }
+
+            Input::MoveWordLeft => self.move_cursor(Some(self.previous_word_start())),
+
+            Input::MoveWordRight => self.move_cursor(Some(self.next_word_end())),
+
+            Input::MoveCursorStart => self.move_cursor(Some(0)),
+
+            Input::MoveCursorEnd => self.move_cursor(Some(self.query.len())),
+
+            Input::SelectPrevious => {
+                let num_rows = self.num_rows();
+                if num_rows == 0 {
+                    return vec![];
+                }
+                self.selected_index = (self.selected_index + num_rows - 1) % num_rows;
+                vec![Effect::SelectionChanged]
+            }
+
+            Input::SelectPreviousPage(rows) => {
+                self.selected_index = self.selected_index.saturating_sub(rows);
+                vec![Effect::SelectionChanged]
+            }
+
+            Input::SelectNextPage(rows) => {
+                let last = self.num_rows().saturating_sub(1);
+                self.selected_index = (self.selected_index + rows).min(last);
+                vec![Effect::SelectionChanged]
+            }
+
+            Input::Select(row) => {
+                if row >= self.num_rows() {
+                    return vec![];
+                }
+                self.selected_index = row;
+                vec![Effect::SelectionChanged]
+            }
+
+            Input::SelectNext => {
+                let num_rows = self.num_rows();
+                if num_rows == 0 {
+                    return vec![];
+                }
+                self.selected_index = (self.selected_index + 1) % num_rows;
+                vec![Effect::SelectionChanged]
+            }
+
+            Input::Accept if !self.marks.is_empty() => vec![Effect::Exit(self.marks.clone())],
+
+            Input::Accept if matches!(self.row(self.selected_index), Some(Row::Suggestion(_))) => {
+                self.set_query(self.suggested_query().unwrap_or_default().to_string());
+                self.query_selected = false;
+                vec![Effect::QueryChanged, self.search()]
+            }
+
+            Input::Accept if matches!(self.row(self.selected_index), Some(Row::Collapsed(_))) => {
+                self.handle(Input::ToggleCollapse)
+            }
+
+            Input::Accept if matches!(self.row(self.selected_index), Some(Row::Create)) => {
+                vec![Effect::Exit(vec![SearchResultEntry::created(&self.query)])]
+            }
+
+            Input::Accept => {
+                let Some(entry) = self.selected_entry() else {
+                    return vec![];
+                };
+                if !(self.drill_down && entry.is_directory()) {
+                    return vec![Effect::Exit(vec![entry.clone()])];
+                }
+
+                self.path_stack.push(entry.identifier.clone());
+                self.set_query(String::new());
+                self.query_selected = false;
+                vec![Effect::QueryChanged, self.search()]
+            }
+
+            Input::ToggleSort => {
+                let selected_identifier =
+                    self.selected_entry().map(|entry| entry.identifier.clone());
+                self.sort_order = self.sort_order.next();
+                if self.sort_order == SortOrder::Frecency && self.frecencies.is_none() {
+                    self.sort_order = self.sort_order.next();
+                }
+                self.sort();
+                self.selected_index = selected_identifier
+                    .and_then(|identifier| self.position(&identifier))
+                    .unwrap_or_else(|| self.first_row());
+                vec![Effect::ResultsChanged]
+            }
+
+            Input::TogglePreview if !self.has_preview => vec![],
+
+            Input::TogglePreview => {
+                self.preview_visible = !self.preview_visible;
+                vec![Effect::PreviewChanged]
+            }
+
+            Input::PreviewFinished {
+                identifier,
+                content,
+            } => {
+                if let Some(ttl) = self.preview_ttl {
+                    let now = Instant::now();
+                    self.preview_cache
+                        .retain(|_, (finished, _)| now.duration_since(*finished) < ttl);
+                    self.preview_cache
+                        .insert(identifier.clone(), (now, content.clone()));
+                }
+                // a preview superseded by a cached one is only cached
+                if self.previewed.as_ref() != Some(&identifier) {
+                    return vec![];
+                }
+                self.preview = Some((identifier, content));
+                vec![Effect::PreviewChanged]
+            }
+
+            Input::RefreshPreview => {
+                let Some(entry) = self.selected_entry().filter(|_| self.preview_visible) else {
+                    return vec![];
+                };
+                let entry = entry.clone();
+                self.preview_cache.remove(&entry.identifier);
+                vec![Effect::Preview(entry)]
+            }
+
+            Input::RecallPrevious => {
+                let index = self.recall.as_ref().map_or(0, |(index, _)| index + 1);
+                let Some(query) = self.query_history.get(index) else {
+                    return vec![];
+                };
+                let draft = match self.recall.take() {
+                    Some((_, draft)) => draft,
+                    None => self.query.clone(),
+                };
+                self.set_query(query.clone());
+                self.query_selected = false;
+                self.recall = Some((index, draft));
+                vec![Effect::QueryChanged, self.search()]
+            }
+
+            Input::RecallNext => {
+                let Some((index, draft)) = self.recall.take() else {
+                    return vec![];
+                };
+                match index.checked_sub(1) {
+                    Some(index) => {
+                        self.set_query(self.query_history[index].clone());
+                        self.recall = Some((index, draft));
+                    }
+                    None => self.set_query(draft),
+                }
+                self.query_selected = false;
+                vec![Effect::QueryChanged, self.search()]
+            }
+
+            Input::ToggleHistory if self.query_history.is_empty() => vec![],
+
+            Input::ToggleHistory => {
+                let queries = self
+                    .query_history
+                    .iter()
+                    .map(|query| SearchResultEntry {
+                        confidence: 1.0,
+                        identifier: query.clone(),
+                        title: query.clone(),
+                        extra: Map::new(),
+                    })
+                    .collect();
+                self.history = Some(Box::new(HistoryPicker {
+                    queries,
+                    saved: Picker::default(),
+                }));
+                self.swap_history();
+                self.filter_history()
+            }
+
+            Input::Copy(field) => match self.selected_entry().map(|entry| field.of(entry)) {
+                Some(Ok(text)) => vec![Effect::Copy(text)],
+                Some(Err(err)) => vec![Effect::Error(err.to_string())],
+                None => vec![],
+            },
+
+            Input::ExecuteSilent(index) => match self.selected_entry() {
+                Some(entry) => vec![Effect::ExecuteSilent {
+                    index,
+                    entry: entry.clone(),
+                }],
+                None => vec![],
+            },
+
+            Input::ExecuteSilentFinished(message) => {
+                self.message = Some(message);
+                vec![Effect::StatusChanged]
+            }
+
+            Input::ToggleMark if !self.multi_select => vec![],
+
+            Input::ToggleMark => {
+                let Some(entry) = self.selected_entry() else {
+                    return vec![];
+                };
+                match self
+                    .marks
+                    .iter()
+                    .position(|marked| marked.identifier == entry.identifier)
+                {
+                    Some(index) => {
+                        self.marks.remove(index);
+                    }
+                    None => self.marks.push(entry.clone()),
+                }
+                self.selected_index = (self.selected_index + 1).min(self.num_rows() - 1);
+                vec![Effect::SelectionChanged, Effect::StatusChanged]
+            }
+
+            Input::TogglePin => {
+                let Some(identifier) = self.selected_entry().map(|entry| entry.identifier.clone())
+                else {
+                    return vec![];
+                };
+                let Some(pinned) = &mut self.pinned else {
+                    return vec![];
+                };
+                match pinned.iter().position(|pinned| *pinned == identifier) {
+                    Some(index) => {
+                        pinned.remove(index);
+                    }
+                    None => pinned.push(identifier.clone()),
+                }
+                let pinned = pinned.clone();
+                self.sort();
+                // the selection follows the entry
+                self.selected_index = self
+                    .position(&identifier)
+                    .unwrap_or_else(|| self.first_row());
+                vec![Effect::PinnedChanged(pinned)]
+            }
+
+            Input::ToggleCollapse => {
+                let source = match self.row(self.selected_index) {
+                    Some(Row::Entry(_, entry)) => entry.source(),
+                    Some(Row::Collapsed(source)) => Some(source.name.as_str()),
+                    _ => None,
+                };
+                let Some(source) = source.map(str::to_string) else {
+                    return vec![];
+                };
+                match self
+                    .collapsed
+                    .iter()
+                    .position(|collapsed| *collapsed == source)
+                {
+                    Some(index) => {
+                        self.collapsed.remove(index);
+                    }
+                    None => self.collapsed.push(source.clone()),
+                }
+                self.sort();
+                // the selection follows the source
+                self.selected_index = (0..self.num_rows())
+                    .find(|index| match self.row(*index) {
+                        Some(Row::Entry(_, entry)) => entry.source() == Some(source.as_str()),
+                        Some(Row::Collapsed(collapsed)) => collapsed.name == source,
+                        _ => false,
+                    })
+                    .unwrap_or_else(|| self.first_row());
+                vec![Effect::ResultsChanged, Effect::StatusChanged]
+            }
+
+            Input::Cancel => vec![Effect::Exit(Vec::new())],
+
+            Input::Refresh if self.searching => vec![],
+
+            Input::Refresh => {
+                let effect = self.start_search(true);
+                self.refreshing = true;
+                vec![effect]
+            }
+
+            // entries of superseded searches may still arrive
+            Input::SearchProgress(_) if !self.searching => vec![],
+
+            Input::SearchProgress(mut entries) => {
+                self.rerank(&mut entries);
+                let selected_identifier = self
+                    .selected_entry()
+                    .map(|entry| entry.identifier.clone())
+                    .filter(|_| self.streamed || self.refreshing || self.navigated());
+                let appended = match &mut self.result {
+                    Some(result) if self.streamed => {
+                        let start = result.results.len();
+                        result.results.extend(entries);
+                        Some(start)
+                    }
+                    _ => {
+                        self.result = Some(SearchResult::new(entries));
+                        None
+                    }
+                };
+                self.streamed = true;
+                self.unfiltered = None;
+                match appended {
+                    // reranking reorders the earlier results too
+                    Some(start) if self.fuzzy_rerank.is_none() => {
+                        if self.dedupe_appended(start) {
+                            self.sort();
+                        } else {
+                            self.sort_appended(start);
+                        }
+                    }
+                    _ => {
+                        self.dedupe();
+                        self.sort_reranked();
+                        self.sort();
+                    }
+                }
+                self.selected_index = selected_identifier
+                    .and_then(|identifier| self.position(&identifier))
+                    .unwrap_or_else(|| self.first_row());
+                vec![Effect::ResultsChanged]
+            }
+
+            Input::SearchFinished(search_result) => {
+                self.searching = false;
+                let refreshing = std::mem::take(&mut self.refreshing);
+                let streamed = std::mem::take(&mut self.streamed);
+                self.unfiltered = None;
+                match search_result {
+                    Ok(mut result) => {
+                        self.search_error = None;
+                        self.rerank(&mut result.results);
+                        let selected_identifier = self
+                            .selected_entry()
+                            .map(|entry| entry.identifier.clone())
+                            .filter(|_| refreshing || streamed || self.navigated());
+                        if refreshing {
+                            self.refresh_marks(&result);
+                        }
+                        self.result = Some(result);
+                        self.dedupe();
+                        self.sort_reranked();
+                        self.sort();
+                        self.selected_index = selected_identifier
+                            .and_then(|identifier| self.position(&identifier))
+                            .unwrap_or_else(|| self.first_row());
+                        vec![Effect::ResultsChanged]
+                    }
+
+                    Err(err) => {
+                        self.result = None;
+                        self.order = Order::default();
+                        self.sources.clear();
+                        self.selected_index = 0;
+                        self.search_error = Some(err.to_string());
+                        vec![Effect::StatusChanged, Effect::ResultsChanged]
+                    }
+                }
+            }
+        }
+    }
+
+    fn handle_in_history(&mut self, input: Input) -> Vec<Effect> {
+        match input {
+            Input::Insert(c) => {
+                self.insert_at_cursor(c);
+                self.filter_history()
+            }
+
+            Input::DeleteBackward if !self.delete_backward() => vec![],
+
+            Input::DeleteBackward => self.filter_history(),
+
+            Input::DeleteWordBackward | Input::KillToStart | Input::KillToEnd
+                if !self.kill_for(&input) =>
+            {
+                vec![]
+            }
+
+            Input::DeleteWordBackward | Input::KillToStart | Input::KillToEnd => {
+                self.filter_history()
+            }
+
+            Input::Yank if !self.yank() => vec![],
+
+            Input::YankPop if !self.yank_pop() => vec![],
+
+            Input::Yank | Input::YankPop => self.filter_history(),
+
+            Input::DeleteForward if !self.delete_forward() => vec![],
+
+            Input::DeleteForward => self.filter_history(),
+
+            Input::Accept => {
+                let Some(entry) = self.selected_entry() else {
+                    return vec![];
+                };
+                let query = entry.identifier.clone();
+                self.close_history();
+                self.set_query(query);
+                self.query_selected = false;
+                vec![Effect::QueryChanged, Effect::ResultsChanged, self.search()]
+            }
+
+            Input::Cancel | Input::ToggleHistory => {
+                self.close_history();
+                vec![Effect::QueryChanged, Effect::ResultsChanged]
+            }
+
+            // searches started before the history was opened update the
+            // picker behind it
+            Input::SearchProgress(_) | Input::SearchFinished(_) => {
+                self.swap_history();
+                let effects = self.handle(input);
+                self.swap_history();
+                effects
+                    .into_iter()
+                    .filter(|effect| matches!(effect, Effect::Error(_) | Effect::StatusChanged))
+                    .collect()
+            }
+
+            Input::SelectPrevious
+            | Input::SelectNext
+            | Input::SelectPreviousPage(_)
+            | Input::SelectNextPage(_)
+            | Input::Select(_)
+            | Input::MoveCursorLeft
+            | Input::MoveCursorRight
+            | Input::MoveWordLeft
+            | Input::MoveWordRight
+            | Input::MoveCursorStart
+            | Input::MoveCursorEnd
+            | Input::ToggleSort
+            | Input::TogglePreview
+            | Input::Copy(_)
+            | Input::ExecuteSilentFinished(_)
+            | Input::PreviewFinished { .. } => {
+                // the picker handles these the same way for past queries
+                let history = self.history.take();
+                let effects = self.handle(input);
+                self.history = history;
+                effects
+            }
+
+            Input::RestoreQuery(_)
+            | Input::ExecuteSilent(_)
+            | Input::ToggleMark
+            | Input::TogglePin
+            | Input::RecallPrevious
+            | Input::RecallNext
+            | Input::Undo
+            | Input::Redo
+            | Input::ToggleCollapse
+            | Input::RefreshPreview
+            | Input::Refresh => vec![],
+        }
+    }
+
+    /// updates the marked entries with the refreshed `result`. marks are kept
+    /// across queries, but a refresh of the same query shows which marked
+    /// entries are gone, so those are dropped
+    fn refresh_marks(&mut self, result: &SearchResult) {
+        if self.marks.is_empty() {
+            return;
+        }
+        let num_marked = self.marks.len();
+        self.marks.retain_mut(|marked| {
+            match result
+                .results
+                .iter()
+                .find(|entry| entry.identifier == marked.identifier)
+            {
+                Some(entry) => {
+                    *marked = entry.clone();
+                    true
+                }
+                None => false,
+            }
+        });
+
+        let num_lost = num_marked - self.marks.len();
+        if num_lost > 0 {
+            self.message = Some(match num_lost {
+                1 => "1 marked entry disappeared".into(),
+                _ => format!("{num_lost} marked entries disappeared"),
+            });
+        }
+    }
+
+    /// shows the past queries matching the query
+    fn filter_history(&mut self) -> Vec<Effect> {
+        let Some(history) = &self.history else {
+            return vec![];
+        };
+        self.result = Some(filter(history.queries.iter().cloned(), &self.query));
+        self.sort();
+        self.selected_index = 0;
+        vec![Effect::QueryChanged, Effect::ResultsChanged]
+    }
+
+    fn close_history(&mut self) {
+        self.swap_history();
+        self.history = None;
+    }
+
+    /// swaps the picker with the one saved by the history picker
+    fn swap_history(&mut self) {
+        let Some(history) = &mut self.history else {
+            return;
+        };
+        let saved = &mut history.saved;
+        std::mem::swap(&mut self.query, &mut saved.query);
+        std::mem::swap(&mut self.query_cursor, &mut saved.query_cursor);
+        std::mem::swap(&mut self.query_selected, &mut saved.query_selected);
+        std::mem::swap(&mut self.result, &mut saved.result);
+        std::mem::swap(&mut self.order, &mut saved.order);
+        std::mem::swap(&mut self.sources, &mut saved.sources);
+        std::mem::swap(&mut self.selected_index, &mut saved.selected_index);
+    }
+
+    /// combines the confidence of `entries` with their fuzzy match score, if
+    /// the results are reranked
+    fn rerank(&self, entries: &mut [SearchResultEntry]) {
+        let Some(weight) = self.fuzzy_rerank else {
+            return;
+        };
+        for entry in entries {
+            let score = match_score(&entry.title, &self.query);
+            entry.confidence = (1.0 - weight) * entry.confidence + weight * score;
+        }
+    }
+
+    /// collapses the results with the same identifier into the one with the
+    /// highest confidence, at the position of the first one
+    fn dedupe(&mut self) {
+        let Some(result) = self.result.as_mut().filter(|_| self.dedupe) else {
+            return;
+        };
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        let mut deduped: Vec<SearchResultEntry> = Vec::with_capacity(result.results.len());
+        for entry in result.results.drain(..) {
+            match positions.get(&entry.identifier) {
+                Some(&position) => {
+                    if entry.confidence > deduped[position].confidence {
+                        deduped[position] = entry;
+                    }
+                }
+                None => {
+                    positions.insert(entry.identifier.clone(), deduped.len());
+                    deduped.push(entry);
+                }
+            }
+        }
+        result.results = deduped;
+    }
+
+    /// collapses the results from `start` on into the earlier ones with the
+    /// same identifier, like [`State::dedupe`], returning whether one of the
+    /// earlier ones was replaced
+    fn dedupe_appended(&mut self, start: usize) -> bool {
+        let Some(result) = self.result.as_mut().filter(|_| self.dedupe) else {
+            return false;
+        };
+        let by_identifier = &mut self.order.by_identifier;
+        let mut replaced = false;
+        for entry in result.results.split_off(start) {
+            match by_identifier.get(&entry.identifier) {
+                Some(&index) => {
+                    if entry.confidence > result.results[index].confidence {
+                        replaced |= index < start;
+                        result.results[index] = entry;
+                    }
+                }
+                None => {
+                    by_identifier.insert(entry.identifier.clone(), result.results.len());
+                    result.results.push(entry);
+                }
+            }
+        }
+        replaced
+    }
+
+    /// orders reranked results by their combined confidence, which becomes
+    /// their backend order
+    fn sort_reranked(&mut self) {
+        if let (Some(_), Some(result)) = (self.fuzzy_rerank, &mut self.result) {
+            // stable, so that ties are kept in backend order
+            result
+                .results
+                .sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+        }
+    }
+
+    /// shows the entries of the results of the last search that match the
+    /// query, until the results for the query arrive
+    fn filter_locally(&mut self) {
+        let Some(unfiltered) = self.unfiltered.take().or_else(|| self.result.clone()) else {
+            return;
+        };
+        let selected_identifier = self
+            .selected_entry()
+            .map(|entry| entry.identifier.clone())
+            .filter(|_| self.navigated());
+        let entries = unfiltered
+            .results
+            .iter()
+            .filter(|entry| matches(&entry.title, &self.query))
+            .cloned()
+            .collect();
+        self.result = Some(SearchResult::new(entries));
+        self.unfiltered = Some(unfiltered);
+        self.sort();
+        self.selected_index = selected_identifier
+            .and_then(|identifier| self.position(&identifier))
+            .unwrap_or_else(|| self.first_row());
+    }
+
+    /// recomputes the display order of the results, leaving out those from
+    /// collapsed sources, and the number of results from each source
+    fn sort(&mut self) {
+        self.order = Order::default();
+        self.sources.clear();
+        self.sort_appended(0);
+    }
+
+    /// merges the results from `start` on into the display order of the
+    /// earlier ones, which must not have changed since it was computed
+    fn sort_appended(&mut self, start: usize) {
+        let Some(result) = &self.result else {
+            self.order = Order::default();
+            self.sources.clear();
+            return;
+        };
+        let results = &result.results;
+        for source in results[start..]
+            .iter()
+            .filter_map(SearchResultEntry::source)
+        {
+            match self.sources.iter_mut().find(|count| count.name == source) {
+                Some(count) => count.count += 1,
+                None => self.sources.push(SourceCount {
+                    name: source.to_string(),
+                    count: 1,
+                    collapsed: self.collapsed.iter().any(|collapsed| collapsed == source),
+                }),
+            }
+        }
+
+        let mut order = std::mem::take(&mut self.order);
+        for (index, entry) in results.iter().enumerate().skip(start) {
+            order
+                .by_identifier
+                .entry(entry.identifier.clone())
+                .or_insert(index);
+        }
+        let mut appended: Vec<usize> = (start..results.len())
+            .filter(|index| !self.is_collapsed(&results[*index]))
+            .collect();
+        let in_backend_order = self.sort_order == SortOrder::Backend
+            && order.indices.is_none()
+            && appended.len() == results.len() - start
+            && appended
+                .iter()
+                .all(|index| self.pin_rank(&results[*index]) == usize::MAX);
+        if in_backend_order {
+            order.positions = (0..results.len()).map(Some).collect();
+            self.order = order;
+            return;
+        }
+
+        // the sorts are stable, so that ties are kept in backend order
+        match self.sort_order {
+            SortOrder::Title => appended.sort_by_cached_key(|index| {
+                let entry = &results[*index];
+                (self.pin_rank(entry), entry.title.to_lowercase())
+            }),
+            SortOrder::Natural => appended.sort_by_cached_key(|index| {
+                let entry = &results[*index];
+                (self.pin_rank(entry), natural_key(&entry.title))
+            }),
+            _ => appended.sort_by(|a, b| self.compare(results, *a, *b)),
+        }
+        let sorted = order.indices.take().unwrap_or_else(|| (0..start).collect());
+        let mut merged = Vec::with_capacity(sorted.len() + appended.len());
+        let mut rest = sorted.as_slice();
+        for index in appended {
+            let before =
+                rest.partition_point(|earlier| self.compare(results, *earlier, index).is_lt());
+            merged.extend_from_slice(&rest[..before]);
+            merged.push(index);
+            rest = &rest[before..];
+        }
+        merged.extend_from_slice(rest);
+
+        order.positions = vec![None; results.len()];
+        for (position, index) in merged.iter().enumerate() {
+            order.positions[*index] = Some(position);
+        }
+        order.indices = Some(merged);
+        self.order = order;
+    }
+
+    /// compares the results at two backend indices in display order, ties
+    /// being kept in backend order
+    fn compare(&self, results: &[SearchResultEntry], a: usize, b: usize) -> Ordering {
+        let (entry_a, entry_b) = (&results[a], &results[b]);
+        let frecency = |entry: &SearchResultEntry| {
+            self.frecencies
+                .as_ref()
+                .and_then(|frecencies| frecencies.get(&entry.identifier))
+                .copied()
+                .unwrap_or(0.0)
+        };
+        self.pin_rank(entry_a)
+            .cmp(&self.pin_rank(entry_b))
+            .then_with(|| match self.sort_order {
+                SortOrder::Backend => Ordering::Equal,
+                SortOrder::Confidence => entry_b.confidence.total_cmp(&entry_a.confidence),
+                SortOrder::Title => entry_a
+                    .title
+                    .to_lowercase()
+                    .cmp(&entry_b.title.to_lowercase()),
+                SortOrder::Natural => natural_key(&entry_a.title).cmp(&natural_key(&entry_b.title)),
+                SortOrder::Frecency => frecency(entry_b).total_cmp(&frecency(entry_a)),
+            })
+            .then(a.cmp(&b))
+    }
+
+    /// the position of `entry` among the pinned entries, which come first in
+    /// the order they were pinned, `usize::MAX` if it isn't pinned
+    fn pin_rank(&self, entry: &SearchResultEntry) -> usize {
+        self.pinned
+            .iter()
+            .flatten()
+            .position(|pinned| *pinned == entry.identifier)
+            .unwrap_or(usize::MAX)
+    }
+
+    fn search(&mut self) -> Effect {
+        self.start_search(false)
+    }
+
+    /// starts a search, whose results are cached ones unless it's a `refresh`
+    fn start_search(&mut self, refresh: bool) -> Effect {
+        self.refreshing = false;
+        self.streamed = false;
+        let min_query_length = self.min_query_length;
+        if self.query.chars().count() < min_query_length {
+            self.searching = false;
+            let mut result = SearchResult::new(Vec::new());
+            result.warnings.push(match min_query_length {
+                1 => "type something to search".to_string(),
+                _ => format!("type at least {min_query_length} characters to search"),
+            });
+            self.result = Some(result);
+            self.unfiltered = None;
+            self.sort();
+            self.selected_index = self.first_row();
+            return Effect::SearchSkipped;
+        }
+        self.searching = true;
+        if self.local_filter && !refresh {
+            self.filter_locally();
+        }
+        Effect::Search {
+            query: self.query.clone(),
+            path: self.path().to_string(),
+            refresh,
+        }
+    }
+}
+
+/// a run of digits or of other characters of a title, numbers sorting before
+/// text
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum NaturalChunk {
+    /// the number of digits, without leading zeros, then the digits, which
+    /// orders numbers by value
+    Number(usize, String),
+    Text(String),
+}
+
+/// the key of `title` in natural order, which compares the numbers in titles
+/// by value and the rest ignoring case
+fn natural_key(title: &str) -> Vec<NaturalChunk> {
+    let mut chunks = Vec::new();
+    let mut rest = title;
+    while let Some(c) = rest.chars().next() {
+        let is_digit = c.is_ascii_digit();
+        let end = rest
+            .find(|c: char| c.is_ascii_digit() != is_digit)
+            .unwrap_or(rest.len());
+        let (chunk, after) = rest.split_at(end);
+        chunks.push(if is_digit {
+            let digits = chunk.trim_start_matches('0');
+            NaturalChunk::Number(digits.len(), digits.to_string())
+        } else {
+            NaturalChunk::Text(chunk.to_lowercase())
+        });
+        rest = after;
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Map;
+
+    use super::{Effect, Input, Row, State};
+    use crate::{Config, SearchResult, SearchResultEntry};
+
+    fn state(config: &str) -> State {
+        let config: Config = serde_json::from_str(config).unwrap();
+        State::new(&config)
+    }
+
+    /// entries ranked in the given order
+    fn result(identifiers: &[&str]) -> SearchResult {
+        let entries = identifiers
+            .iter()
+            .enumerate()
+            .map(|(index, identifier)| SearchResultEntry {
+                confidence: 1.0 - index as f64 / identifiers.len() as f64,
+                identifier: identifier.to_string(),
+                title: identifier.to_string(),
+                extra: Map::new(),
+            })
+            .collect();
+        SearchResult::new(entries)
+    }
+
+    fn searched(config: &str, identifiers: &[&str]) -> State {
+        let mut state = state(config);
+        state.update(Input::Insert('x'));
+        state.update(Input::SearchFinished(Ok(result(identifiers))));
+        state
+    }
+
+    fn selected(state: &State) -> Option<String> {
+        match state.row(state.selected_index()) {
+            Some(Row::Entry(_, entry)) => Some(entry.identifier.clone()),
+            _ => None,
+        }
+    }
+
+    fn type_text(state: &mut State, text: &str) {
+        for c in text.chars() {
+            state.update(Input::Insert(c));
+        }
+    }
+
+    const CONFIG: &str = r#"{"source": {"executable": "true"}}"#;
+
+    #[test]
+    fn selection_wraps_around() {
+        let mut state = searched(CONFIG, &["a", "b", "c"]);
+        assert_eq!(selected(&state).as_deref(), Some("a"));
+        let effects = state.update(Input::SelectNext);
+        assert!(matches!(effects[..], [Effect::SelectionChanged]));
+        state.update(Input::SelectNext);
+        assert_eq!(selected(&state).as_deref(), Some("c"));
+        state.update(Input::SelectNext);
+        assert_eq!(selected(&state).as_deref(), Some("a"));
+        state.update(Input::SelectPrevious);
+        assert_eq!(selected(&state).as_deref(), Some("c"));
+    }
+
+    #[test]
+    fn pages_stop_at_the_ends() {
+        let mut state = searched(CONFIG, &["a", "b", "c", "d", "e"]);
+        state.update(Input::SelectNextPage(3));
+        assert_eq!(state.selected_index(), 3);
+        state.update(Input::SelectNextPage(3));
+        assert_eq!(state.selected_index(), 4);
+        state.update(Input::SelectPreviousPage(10));
+        assert_eq!(state.selected_index(), 0);
+    }
+
+    #[test]
+    fn selection_without_results_does_nothing() {
+        let mut state = searched(CONFIG, &[]);
+        assert!(state.update(Input::SelectNext).is_empty());
+        assert!(state.update(Input::SelectPrevious).is_empty());
+        assert_eq!(state.selected_index(), 0);
+    }
+
+    #[test]
+    fn marked_entries_are_accepted_together() {
+        let config = r#"{"source": {"executable": "true"}, "multi_select": true}"#;
+        let mut state = searched(config, &["a", "b", "c"]);
+        state.update(Input::ToggleMark);
+        assert_eq!(selected(&state).as_deref(), Some("b"));
+        state.update(Input::SelectNext);
+        state.update(Input::ToggleMark);
+        // the selection stays on the last row
+        assert_eq!(selected(&state).as_deref(), Some("c"));
+        assert_eq!(state.num_marked(), 2);
+
+        let effects = state.update(Input::Accept);
+        let [Effect::Exit(accepted)] = &effects[..] else {
+            panic!("accepting didn't exit");
+        };
+        let accepted: Vec<&str> = accepted.iter().map(|entry| &*entry.identifier).collect();
+        assert_eq!(accepted, ["a", "c"]);
+    }
+
+    #[test]
+    fn marking_again_unmarks() {
+        let config = r#"{"source": {"executable": "true"}, "multi_select": true}"#;
+        let mut state = searched(config, &["a", "b"]);
+        state.update(Input::ToggleMark);
+        state.update(Input::SelectPrevious);
+        state.update(Input::ToggleMark);
+        assert_eq!(state.num_marked(), 0);
+        let effects = state.update(Input::Accept);
+        assert!(matches!(&effects[..], [Effect::Exit(accepted)] if accepted.len() == 1));
+    }
+
+    /// the identifiers of the shown entries
+    fn shown(state: &State) -> Vec<String> {
+        (0..state.num_rows())
+            .filter_map(|index| match state.row(index) {
+                Some(Row::Entry(_, entry)) => Some(entry.identifier.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn streamed_entries_are_merged_in_order() {
+        let config = r#"{"source": {"executable": "true"}, "sort": "title"}"#;
+        let mut state = state(config);
+        state.update(Input::Insert('x'));
+        state.update(Input::SearchProgress(result(&["d", "b"]).results));
+        state.update(Input::SelectNext);
+        state.update(Input::SearchProgress(result(&["c", "a", "e"]).results));
+        assert_eq!(shown(&state), ["a", "b", "c", "d", "e"]);
+        // the selection follows the entry
+        assert_eq!(selected(&state).as_deref(), Some("d"));
+        let entry = state.selected_entry().unwrap().clone();
+        assert_eq!(state.rank(&entry), Some(4));
+    }
+
+    #[test]
+    fn streamed_duplicates_replace_worse_entries() {
+        let config = r#"{"source": {"executable": "true"}, "sort": "confidence", "dedupe": true}"#;
+        let mut state = state(config);
+        state.update(Input::Insert('x'));
+        state.update(Input::SearchProgress(result(&["a", "b", "c"]).results));
+        let mut better = result(&["c", "d"]).results;
+        better[0].confidence = 2.0;
+        state.update(Input::SearchProgress(better));
+        assert_eq!(shown(&state), ["c", "a", "b", "d"]);
+    }
+
+    #[test]
+    fn marking_requires_multi_select() {
+        let mut state = searched(CONFIG, &["a", "b"]);
+        assert!(state.update(Input::ToggleMark).is_empty());
+        assert_eq!(state.num_marked(), 0);
+    }
+
+    #[test]
+    fn recalling_past_queries_returns_to_the_draft() {
+        let mut state = state(CONFIG);
+        state.set_query_history(vec!["newest".into(), "older".into()]);
+        type_text(&mut state, "dr");
+
+        let effects = state.update(Input::RecallPrevious);
+        assert!(matches!(
+            &effects[..],
+            [Effect::QueryChanged, Effect::Search { query, .. }] if query == "newest"
+        ));
+        state.update(Input::RecallPrevious);
+        assert_eq!(state.query(), "older");
+        assert!(state.update(Input::RecallPrevious).is_empty());
+        state.update(Input::RecallNext);
+        assert_eq!(state.query(), "newest");
+        state.update(Input::RecallNext);
+        assert_eq!(state.query(), "dr");
+        assert!(state.update(Input::RecallNext).is_empty());
+    }
+
+    #[test]
+    fn editing_forgets_the_recalled_query() {
+        let mut state = state(CONFIG);
+        state.set_query_history(vec!["newest".into()]);
+        state.update(Input::RecallPrevious);
+        state.update(Input::Insert('!'));
+        assert!(state.update(Input::RecallNext).is_empty());
+        assert_eq!(state.query(), "newest!");
+    }
+
+    #[test]
+    fn undo_and_redo_edits() {
+        let mut state = state(CONFIG);
+        type_text(&mut state, "foo bar");
+        state.update(Input::KillToStart);
+        assert_eq!(state.query(), "");
+
+        let effects = state.update(Input::Undo);
+        assert!(matches!(
+            &effects[..],
+            [Effect::QueryChanged, Effect::Search { query, .. }] if query == "foo bar"
+        ));
+        assert_eq!(state.query_cursor(), "foo bar".len());
+        // typing a word is undone at once
+        state.update(Input::Undo);
+        assert_eq!(state.query(), "foo ");
+        state.update(Input::Undo);
+        assert_eq!(state.query(), "foo");
+        state.update(Input::Redo);
+        state.update(Input::Redo);
+        assert_eq!(state.query(), "foo bar");
+        state.update(Input::Redo);
+        assert_eq!(state.query(), "");
+        assert!(state.update(Input::Redo).is_empty());
+    }
+
+    #[test]
+    fn editing_after_undo_drops_the_redo() {
+        let mut state = state(CONFIG);
+        type_text(&mut state, "ab");
+        state.update(Input::DeleteBackward);
+        state.update(Input::Undo);
+        assert_eq!(state.query(), "ab");
+        state.update(Input::Insert(' '));
+        assert!(state.update(Input::Redo).is_empty());
+        assert_eq!(state.query(), "ab ");
+    }
+
+    #[test]
+    fn refresh_keeps_the_selected_entry() {
+        let mut state = searched(CONFIG, &["a", "b", "c", "d", "e"]);
+        state.update(Input::SelectPrevious);
+        assert_eq!(selected(&state).as_deref(), Some("e"));
+        let effects = state.update(Input::Refresh);
+        assert!(matches!(
+            &effects[..],
+            [Effect::Search { refresh: true, .. }]
+        ));
+        let effects = state.update(Input::SearchFinished(Ok(result(&["a", "e"]))));
+        assert!(matches!(&effects[..], [Effect::ResultsChanged]));
+        assert_eq!(selected(&state).as_deref(), Some("e"));
+        assert_eq!(state.selected_index(), 1);
+    }
+
+    #[test]
+    fn refresh_that_shrinks_the_list_keeps_the_selection_in_it() {
+        let mut state = searched(CONFIG, &["a", "b", "c", "d", "e"]);
+        state.update(Input::SelectPrevious);
+        state.update(Input::Refresh);
+        state.update(Input::SearchFinished(Ok(result(&["a", "b"]))));
+        assert!(state.selected_index() < state.num_rows());
+        assert_eq!(selected(&state).as_deref(), Some("a"));
+
+        state.update(Input::Refresh);
+        state.update(Input::SearchFinished(Ok(result(&[]))));
+        assert_eq!(state.num_rows(), 0);
+        assert_eq!(selected(&state), None);
+    }
+}
@@ -0,0 +1,288 @@
+use std::{
+    borrow::Cow,
+    env,
+    fmt::Write,
+    sync::LazyLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Deserializer};
+use serde_json::{Map, Value};
+use tinytemplate::{error::Error, TinyTemplate};
+
+pub struct Template<'a> {
+    template: TinyTemplate<'a>,
+    /// whether the template refers to `{env.*}`, which is only added to the
+    /// context if it does
+    uses_env: bool,
+    /// the `extra.*` paths the template refers to, which are null for
+    /// entries that don't have them
+    extra_paths: Vec<Vec<&'a str>>,
+}
+
+/// the environment variables, available to templates as `{env.*}`
+static ENV: LazyLock<Value> = LazyLock::new(|| {
+    Value::Object(
+        env::vars()
+            .map(|(name, value)| (name, Value::String(value)))
+            .collect::<Map<_, _>>(),
+    )
+});
+
+impl<'a> Template<'a> {
+    pub fn new(template_string: &'a str) -> anyhow::Result<Self> {
+        let mut template = TinyTemplate::new();
+        // the output is never html, so there is no point in escaping it
+        template.set_default_formatter(&format_unescaped);
+        template.add_formatter("percent", percent);
+        template.add_formatter("stars", stars);
+        template.add_formatter("fixed1", |value, output| fixed(value, output, 1));
+        template.add_formatter("fixed2", |value, output| fixed(value, output, 2));
+        template.add_formatter("fixed3", |value, output| fixed(value, output, 3));
+        template.add_formatter("relative_time", relative_time);
+        template.add_formatter("filesize", filesize);
+        template.add_formatter("url", url);
+        template.add_template("main", template_string)?;
+        Ok(Self {
+            template,
+            uses_env: template_string.contains("env."),
+            extra_paths: extra_paths(template_string),
+        })
+    }
+
+    pub fn render<C: serde::Serialize>(&self, context: &C) -> anyhow::Result<String> {
+        if !self.uses_env && self.extra_paths.is_empty() {
+            return Ok(self.template.render("main", context)?);
+        }
+        let mut context = serde_json::to_value(context)?;
+        if let Value::Object(context) = &mut context {
+            if self.uses_env {
+                context.insert("env".into(), ENV.clone());
+            }
+            if let Some(Value::Object(extra)) = context.get_mut("extra") {
+                for path in &self.extra_paths {
+                    insert_missing(extra, path);
+                }
+            }
+        }
+        Ok(self.template.render("main", &context)?)
+    }
+}
+
+/// the paths after `extra.` in `template_string`, e.g. `["meta", "size"]` for
+/// `{extra.meta.size | filesize}`
+fn extra_paths(template_string: &str) -> Vec<Vec<&str>> {
+    let is_path = |c: char| c.is_alphanumeric() || c == '_' || c == '.';
+    template_string
+        .match_indices("extra.")
+        .filter(|(index, _)| !template_string[..*index].ends_with(is_path))
+        .map(|(index, prefix)| {
+            let path = &template_string[index + prefix.len()..];
+            let end = path.find(|c| !is_path(c)).unwrap_or(path.len());
+            path[..end]
+                .split('.')
+                .filter(|field| !field.is_empty())
+                .collect()
+        })
+        .filter(|path: &Vec<&str>| !path.is_empty())
+        .collect()
+}
+
+/// sets `path` in `fields` to null if it's missing, so that templates can
+/// refer to fields that only some entries have
+fn insert_missing(fields: &mut Map<String, Value>, path: &[&str]) {
+    let Some((field, rest)) = path.split_first() else {
+        return;
+    };
+    let value = fields.entry(*field).or_insert_with(|| match rest {
+        [] => Value::Null,
+        _ => Value::Object(Map::new()),
+    });
+    if let Value::Object(fields) = value {
+        insert_missing(fields, rest);
+    }
+}
+
+/// like [`tinytemplate::format_unescaped`], but writes arrays and objects as
+/// json instead of failing
+fn format_unescaped(value: &Value, output: &mut String) -> tinytemplate::error::Result<()> {
+    match value {
+        Value::Array(_) | Value::Object(_) => {
+            write!(output, "{value}")?;
+            Ok(())
+        }
+        value => tinytemplate::format_unescaped(value, output),
+    }
+}
+
+/// replaces `$VAR` and `${VAR}` with the value of the environment variable,
+/// escaped if `string` is a template. references to unset variables are kept
+/// as they are, so that shell snippets using their own variables still work
+pub fn expand_env(string: &str, template: bool) -> Cow<'_, str> {
+    if !string.contains('$') {
+        return Cow::Borrowed(string);
+    }
+    let is_name_start = |c: char| c.is_ascii_alphabetic() || c == '_';
+    let is_name = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut expanded = String::with_capacity(string.len());
+    let mut rest = string;
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        let (name, reference_len) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            },
+            None => {
+                let end = after.find(|c| !is_name(c)).unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+        let valid = name.starts_with(is_name_start) && name.chars().all(is_name);
+        match env::var(name).ok().filter(|_| valid) {
+            Some(value) if template => {
+                expanded.push_str(&value.replace('{', "\\{"));
+                rest = &after[reference_len..];
+            }
+            Some(value) => {
+                expanded.push_str(&value);
+                rest = &after[reference_len..];
+            }
+            None => {
+                expanded.push('$');
+                rest = after;
+            }
+        }
+    }
+    expanded.push_str(rest);
+    Cow::Owned(expanded)
+}
+
+/// deserializes a template with [`expand_env`] applied
+pub fn deserialize_expanded<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<String, D::Error> {
+    let string = String::deserialize(deserializer)?;
+    Ok(expand_env(&string, true).into_owned())
+}
+
+/// deserializes an optional template with [`expand_env`] applied
+pub fn deserialize_expanded_option<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    let string = Option::<String>::deserialize(deserializer)?;
+    Ok(string.map(|string| expand_env(&string, true).into_owned()))
+}
+
+/// deserializes a list of templates with [`expand_env`] applied
+pub fn deserialize_expanded_list<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<String>, D::Error> {
+    let strings = Vec::<String>::deserialize(deserializer)?;
+    Ok(strings
+        .iter()
+        .map(|string| expand_env(string, true).into_owned())
+        .collect())
+}
+
+fn number(value: &Value) -> tinytemplate::error::Result<f64> {
+    let number = match value {
+        Value::String(string) => string.trim().parse().ok(),
+        value => value.as_f64(),
+    };
+    number.ok_or_else(|| Error::GenericError {
+        msg: format!("expected a number, got {value}"),
+    })
+}
+
+/// formats a confidence in `[0, 1]` as a percentage, e.g. `83%`
+fn percent(value: &Value, output: &mut String) -> tinytemplate::error::Result<()> {
+    write!(output, "{:.0}%", number(value)? * 100.0)?;
+    Ok(())
+}
+
+/// formats a confidence in `[0, 1]` as a five-star rating, e.g. `★★★★☆`
+fn stars(value: &Value, output: &mut String) -> tinytemplate::error::Result<()> {
+    const NUM_STARS: usize = 5;
+    let filled = (number(value)?.clamp(0.0, 1.0) * NUM_STARS as f64).round() as usize;
+    output.extend(std::iter::repeat_n('★', filled));
+    output.extend(std::iter::repeat_n('☆', NUM_STARS - filled));
+    Ok(())
+}
+
+fn fixed(value: &Value, output: &mut String, precision: usize) -> tinytemplate::error::Result<()> {
+    write!(output, "{:.*}", precision, number(value)?)?;
+    Ok(())
+}
+
+/// formats a UNIX timestamp (in seconds) relative to the current time, e.g.
+/// `3 days ago`
+fn relative_time(value: &Value, output: &mut String) -> tinytemplate::error::Result<()> {
+    const UNITS: [(&str, f64); 6] = [
+        ("year", 365.0 * 24.0 * 60.0 * 60.0),
+        ("month", 30.0 * 24.0 * 60.0 * 60.0),
+        ("week", 7.0 * 24.0 * 60.0 * 60.0),
+        ("day", 24.0 * 60.0 * 60.0),
+        ("hour", 60.0 * 60.0),
+        ("minute", 60.0),
+    ];
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |duration| duration.as_secs_f64());
+    let delta = now - number(value)?;
+    let Some((unit, amount)) = UNITS
+        .iter()
+        .map(|(unit, seconds)| (unit, (delta.abs() / seconds).floor()))
+        .find(|(_, amount)| *amount >= 1.0)
+    else {
+        output.push_str("just now");
+        return Ok(());
+    };
+
+    let plural = if amount == 1.0 { "" } else { "s" };
+    if delta >= 0.0 {
+        write!(output, "{amount} {unit}{plural} ago")?;
+    } else {
+        write!(output, "in {amount} {unit}{plural}")?;
+    }
+    Ok(())
+}
+
+/// formats a byte count as a human-readable size, e.g. `1.5 MiB`
+fn filesize(value: &Value, output: &mut String) -> tinytemplate::error::Result<()> {
+    const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+    let mut size = number(value)?;
+    if size.abs() < 1024.0 {
+        write!(output, "{size} B")?;
+        return Ok(());
+    }
+
+    let mut unit = UNITS[0];
+    for next_unit in UNITS {
+        unit = next_unit;
+        size /= 1024.0;
+        if size.abs() < 1024.0 {
+            break;
+        }
+    }
+    write!(output, "{size:.1} {unit}")?;
+    Ok(())
+}
+
+/// percent-encodes a value for use inside of an url, e.g. as a query parameter
+fn url(value: &Value, output: &mut String) -> tinytemplate::error::Result<()> {
+    let mut text = String::new();
+    tinytemplate::format_unescaped(value, &mut text)?;
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                output.push(byte as char)
+            }
+            byte => write!(output, "%{byte:02X}")?,
+        }
+    }
+    Ok(())
+}
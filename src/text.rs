@@ -0,0 +1,242 @@
+use std::{borrow::Cow, iter::Peekable, str::Chars};
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// returns the longest suffix of `text` that occupies at most `max_width`
+/// terminal cells, without splitting graphemes
+pub fn visible_tail(text: &str, max_width: usize) -> &str {
+    let mut width = 0;
+    for (index, grapheme) in text.grapheme_indices(true).rev() {
+        width += grapheme.width();
+        if width > max_width {
+            return &text[index + grapheme.len()..];
+        }
+    }
+
+    text
+}
+
+/// replaces control characters in backend-provided text with printable
+/// representations, so that the backend cannot inject escape sequences (cursor
+/// movement, window title changes, etc.) into the terminal
+pub fn sanitize(text: &str) -> Cow<'_, str> {
+    if !text.chars().any(char::is_control) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut sanitized = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\t' | '\n' | '\r' => sanitized.push(' '),
+            // caret notation, e.g. ESC becomes ^[
+            '\0'..='\x1f' => {
+                sanitized.push('^');
+                sanitized.push((c as u8 + b'@') as char);
+            }
+            '\x7f' => sanitized.push_str("^?"),
+            c if c.is_control() => sanitized.extend(c.escape_unicode()),
+            c => sanitized.push(c),
+        }
+    }
+
+    Cow::Owned(sanitized)
+}
+
+/// like [`sanitize`], but keeps SGR sequences (colors, bold, etc.) while
+/// removing every other escape sequence
+pub fn sanitize_keeping_sgr(text: &str) -> Cow<'_, str> {
+    if !text.chars().any(char::is_control) {
+        return Cow::Borrowed(text);
+    }
+
+    // the text between SGR sequences is cleaned like with the strip mode
+    let mut sanitized = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut start = 0;
+    while let Some(offset) = rest[start..].find('\x1b') {
+        let index = start + offset;
+        match sgr_len(&rest[index..]) {
+            Some(len) => {
+                sanitized.push_str(&sanitize(&strip_ansi(&rest[..index])));
+                sanitized.push_str(&rest[index..index + len]);
+                rest = &rest[index + len..];
+                start = 0;
+            }
+            None => start = index + 1,
+        }
+    }
+    sanitized.push_str(&sanitize(&strip_ansi(rest)));
+
+    Cow::Owned(sanitized)
+}
+
+/// the chars of `text` along with their byte indices, skipping SGR sequences
+pub fn visible_char_indices(text: &str) -> Vec<(usize, char)> {
+    let mut chars = Vec::with_capacity(text.len());
+    let mut index = 0;
+    while let Some(c) = text[index..].chars().next() {
+        if let Some(len) = sgr_len(&text[index..]) {
+            index += len;
+            continue;
+        }
+        chars.push((index, c));
+        index += c.len_utf8();
+    }
+    chars
+}
+
+/// the length of the SGR sequence (e.g. `ESC [ 1 ; 31 m`) at the start of
+/// `text`, if there is one
+fn sgr_len(text: &str) -> Option<usize> {
+    let params = text.strip_prefix("\x1b[")?;
+    let end = params.find(|c: char| !(c.is_ascii_digit() || c == ';' || c == ':'))?;
+    params[end..]
+        .starts_with('m')
+        .then_some("\x1b[".len() + end + 1)
+}
+
+/// inserts `style` after every SGR sequence in `text` that resets the
+/// foreground, background or intensity, so that e.g. the highlight of the
+/// selected row isn't lost in the middle of it
+pub fn reapply_after_resets<'a>(text: &'a str, style: &str) -> Cow<'a, str> {
+    if style.is_empty() || !text.contains('\x1b') {
+        return Cow::Borrowed(text);
+    }
+
+    let mut reapplied = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('\x1b') {
+        reapplied.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match sgr_len(rest) {
+            Some(len) => {
+                let params = &rest["\x1b[".len()..len - 1];
+                reapplied.push_str(&rest[..len]);
+                if resets_style(params) {
+                    reapplied.push_str(style);
+                }
+                rest = &rest[len..];
+            }
+            None => {
+                reapplied.push('\x1b');
+                rest = &rest[1..];
+            }
+        }
+    }
+    reapplied.push_str(rest);
+
+    Cow::Owned(reapplied)
+}
+
+/// whether the SGR parameters `params` reset the foreground, background or
+/// intensity
+fn resets_style(params: &str) -> bool {
+    let mut params = params.split(';');
+    while let Some(param) = params.next() {
+        match param {
+            "" | "0" | "22" | "39" | "49" => return true,
+            // extended colors, whose arguments aren't attributes themselves
+            "38" | "48" | "58" => match params.next() {
+                Some("5") => {
+                    params.next();
+                }
+                Some("2") => {
+                    params.nth(2);
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+    false
+}
+
+/// truncates `text` so that it occupies at most `max_width` terminal cells,
+/// returning the truncated string along with its display width. SGR sequences
+/// don't take up any cells and graphemes aren't split
+pub fn truncate_to_width(text: &str, max_width: usize) -> (String, usize) {
+    let mut width = 0;
+    let mut index = 0;
+    // graphemes end before escape sequences, since those are control chars
+    while let Some(grapheme) = text[index..].graphemes(true).next() {
+        if let Some(len) = sgr_len(&text[index..]) {
+            index += len;
+            continue;
+        }
+        let grapheme_width = grapheme.width();
+        if width + grapheme_width > max_width {
+            break;
+        }
+        width += grapheme_width;
+        index += grapheme.len();
+    }
+
+    (text[..index].to_owned(), width)
+}
+
+/// like [`truncate_to_width`], but ends text that doesn't fit with an ellipsis
+pub fn truncate_with_ellipsis(text: &str, max_width: usize) -> (String, usize) {
+    let (truncated, width) = truncate_to_width(text, max_width);
+    if truncated.len() == text.len() || max_width == 0 {
+        return (truncated, width);
+    }
+    let (mut truncated, width) = truncate_to_width(text, max_width - 1);
+    truncated.push('…');
+    (truncated, width + 1)
+}
+
+/// removes ANSI escape sequences (colors, cursor movement, OSC strings, etc.)
+/// from `text`
+pub fn strip_ansi(text: &str) -> Cow<'_, str> {
+    if !text.contains(['\x1b', '\u{9b}']) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                Some('[') => skip_csi(&mut chars),
+                Some(']' | 'P' | 'X' | '^' | '_') => skip_string(&mut chars),
+                // sequences with intermediate bytes, e.g. ESC ( B
+                Some('\x20'..='\x2f') => {
+                    for c in chars.by_ref() {
+                        if ('\x30'..='\x7e').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // two-character sequences, e.g. ESC 7
+                _ => {}
+            },
+            '\u{9b}' => skip_csi(&mut chars),
+            c => stripped.push(c),
+        }
+    }
+
+    Cow::Owned(stripped)
+}
+
+fn skip_csi(chars: &mut Peekable<Chars>) {
+    for c in chars.by_ref() {
+        if ('\x40'..='\x7e').contains(&c) {
+            break;
+        }
+    }
+}
+
+/// skips an OSC/DCS-like string, terminated by BEL or ST
+fn skip_string(chars: &mut Peekable<Chars>) {
+    while let Some(c) = chars.next() {
+        match c {
+            '\x07' | '\u{9c}' => break,
+            '\x1b' => {
+                chars.next_if_eq(&'\\');
+                break;
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! the colors of the terminal interface
+
+use std::borrow::Cow;
+
+use crossterm::style::Color;
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde::Deserialize;
+
+#[derive(Deserialize, JsonSchema, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    /// the foreground of the selected row
+    pub selected_fg: ThemeColor,
+    /// the background of the selected row
+    pub selected_bg: ThemeColor,
+    /// the foreground of the other rows
+    pub text: ThemeColor,
+    /// the prompt in front of the query
+    pub prompt: ThemeColor,
+    /// the header below the query, see `header`
+    pub header: ThemeColor,
+    /// errors, e.g. from a failed search, and slow searches
+    pub error: ThemeColor,
+    /// the rest of the status line above the results
+    pub status: ThemeColor,
+    /// the parts of the results that match the query, see `highlight_matches`
+    pub matched: ThemeColor,
+    /// the parts of the selected row that match the query
+    pub selected_matched: ThemeColor,
+    /// the foreground of stale results (see `dim_stale_results`), which are
+    /// dimmed instead if unset
+    pub stale: Option<ThemeColor>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            selected_fg: ThemeColor(Color::Black),
+            selected_bg: ThemeColor(Color::White),
+            text: ThemeColor(Color::Reset),
+            prompt: ThemeColor(Color::Reset),
+            header: ThemeColor(Color::Reset),
+            error: ThemeColor(Color::Red),
+            status: ThemeColor(Color::Yellow),
+            matched: ThemeColor(Color::Cyan),
+            selected_matched: ThemeColor(Color::DarkBlue),
+            stale: None,
+        }
+    }
+}
+
+/// a color name (e.g. `"red"` or `"dark_blue"`), `"default"` for the
+/// terminal's default color, a 256-color palette index or a hex color like
+/// `"#ff8800"`
+#[derive(Deserialize, Clone, Copy)]
+#[serde(try_from = "String")]
+pub struct ThemeColor(pub Color);
+
+impl TryFrom<String> for ThemeColor {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, String> {
+        let invalid = || format!("invalid color: {name}");
+        let color = if name == "default" {
+            Color::Reset
+        } else if let Some(hex) = name.strip_prefix('#') {
+            if hex.len() != 6 {
+                return Err(invalid());
+            }
+            let value = u32::from_str_radix(hex, 16).map_err(|_| invalid())?;
+            let [_, r, g, b] = value.to_be_bytes();
+            Color::Rgb { r, g, b }
+        } else if let Ok(value) = name.parse() {
+            Color::AnsiValue(value)
+        } else {
+            Color::try_from(name.as_str()).map_err(|()| invalid())?
+        };
+        Ok(Self(color))
+    }
+}
+
+impl JsonSchema for ThemeColor {
+    fn schema_name() -> Cow<'static, str> {
+        "ThemeColor".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "type": "string",
+            "description": "a color name, \"default\", a palette index or a hex color"
+        })
+    }
+}